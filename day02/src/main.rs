@@ -1,22 +1,22 @@
 use intcode::Program;
 
-fn output(mut memory: Vec<i64>, noun: i64, verb: i64) -> i64 {
-    memory[1] = noun;
-    memory[2] = verb;
-    let mut program = Program::new(memory);
-    program.run("".as_bytes(), Vec::new());
+fn output(program: &Program, noun: i64, verb: i64) -> i64 {
+    let mut program = program.clone();
+    program.poke(1, noun);
+    program.poke(2, verb);
+    program.run(None, Vec::new()).unwrap();
     program.memory()[0]
 }
 
 fn main() {
-    let memory = intcode::read_program_file("input").unwrap();
+    let program = Program::from_file("input").unwrap();
 
-    let part1 = output(memory.clone(), 12, 2);
+    let part1 = output(&program, 12, 2);
     println!("{part1}");
 
     for noun in 0..=99 {
         for verb in 0..=99 {
-            if output(memory.clone(), noun, verb) == 19690720 {
+            if output(&program, noun, verb) == 19690720 {
                 println!("{}", 100 * noun + verb);
             }
         }
@@ -45,8 +45,8 @@ mod tests {
 
     #[test]
     fn answers() {
-        let memory = intcode::read_program_file("input").unwrap();
-        assert_eq!(output(memory.clone(), 12, 2), 9581917);
-        assert_eq!(output(memory.clone(), 25, 5), 19690720);
+        let program = Program::from_file("input").unwrap();
+        assert_eq!(output(&program, 12, 2), 9581917);
+        assert_eq!(output(&program, 25, 5), 19690720);
     }
 }
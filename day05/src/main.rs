@@ -1,8 +1,18 @@
-use intcode::Program;
+use std::io;
+
+use intcode::{
+    io::{TextInput, TextOutput},
+    Program,
+};
 
 fn main() {
     let mut program = Program::from_file("input").unwrap();
-    program.run(std::io::stdin().lock(), std::io::stdout());
+    program
+        .run(
+            TextInput::new(io::stdin().lock()),
+            TextOutput::new(io::stdout()),
+        )
+        .unwrap();
 }
 
 #[cfg(test)]
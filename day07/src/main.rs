@@ -1,24 +1,19 @@
 use itertools::Itertools;
 
-use intcode::Program;
+use intcode::{topology::Topology, Program};
 
 fn run_amplifiers(program: &Program, phases: Vec<i64>) -> i64 {
-    let mut programs = Vec::new();
-    for phase in phases {
-        let mut program = program.clone();
-        program.run_with_input(phase, Vec::new());
-        programs.push(program);
-    }
-    let mut prev_output = 0;
-    let mut halted = false;
-    while !halted {
-        for program in &mut programs {
-            let mut output: Vec<u8> = Vec::new();
-            halted = program.run_with_input(prev_output, &mut output);
-            prev_output = String::from_utf8(output).unwrap().trim().parse().unwrap();
-        }
-    }
-    prev_output
+    let mut topology = Topology::new();
+    let amplifiers: Vec<_> = phases
+        .into_iter()
+        .map(|phase| topology.node(program.clone(), [phase]))
+        .collect();
+    topology.seed(amplifiers[0], [0]);
+    // the feedback edge from the last amplifier carries the thruster signal
+    let feedback = *topology.cycle(&amplifiers).last().unwrap();
+    let finished = topology.run().unwrap();
+    assert!(finished.halted());
+    *finished.last(feedback).unwrap()
 }
 
 fn highest_signal(program: &Program, phases: [i64; 5]) -> i64 {
@@ -49,18 +44,18 @@ mod tests {
     #[test]
     fn run_with_input_simple() {
         let mut program = Program::from_file("../day07/input").unwrap();
-        assert!(!program.run_with_input(0, Vec::new()));
-        assert!(program.run_with_input(0, Vec::new()));
+        assert!(!program.run_with_input(0, Vec::new()).unwrap());
+        assert!(program.run_with_input(0, Vec::new()).unwrap());
     }
 
     #[test]
     fn run_with_input_many() {
         let mut program = Program::from_file("../day07/input").unwrap();
-        assert!(!program.run_with_input(5, Vec::new()));
+        assert!(!program.run_with_input(5, Vec::new()).unwrap());
         for _ in 0..9 {
-            assert!(!program.run_with_input(0, Vec::new()));
+            assert!(!program.run_with_input(0, Vec::new()).unwrap());
         }
-        assert!(program.run_with_input(0, Vec::new()));
+        assert!(program.run_with_input(0, Vec::new()).unwrap());
     }
 
     #[test]
@@ -1,10 +1,18 @@
 use std::io;
 
-use intcode::Program;
+use intcode::{
+    io::{TextInput, TextOutput},
+    Program,
+};
 
 fn main() {
     let mut program = Program::from_file("input").unwrap();
-    program.run(io::stdin().lock(), io::stdout());
+    program
+        .run(
+            TextInput::new(io::stdin().lock()),
+            TextOutput::new(io::stdout()),
+        )
+        .unwrap();
 }
 
 #[cfg(test)]
@@ -49,24 +49,24 @@ fn run_robot(mut program: Program, starting_color: Color) -> HashMap<Coord, Colo
     let mut coord = (0, 0);
     let mut panels = HashMap::from([(coord, starting_color)]);
 
-    let mut halted = false;
-    while !halted {
+    loop {
         let input = match panels.get(&coord) {
             Some(Color::White) => 1,
             _ => 0,
         };
-        let mut output = Vec::new();
-        halted = program.run_with_input(input, &mut output);
-        let outputs: Vec<&str> = std::str::from_utf8(&output).unwrap().split('\n').collect();
-        let color = match outputs[0] {
-            "0" => Color::Black,
-            "1" => Color::White,
-            s => panic!("bad color output '{s}'"),
+        let mut outputs = program.outputs([input]);
+        let Some(color) = outputs.next() else {
+            break;
         };
-        direction = match outputs[1] {
-            "0" => turn_left(&direction),
-            "1" => turn_right(&direction),
-            s => panic!("bad direction output '{s}'"),
+        let color = match color.unwrap() {
+            0 => Color::Black,
+            1 => Color::White,
+            x => panic!("bad color output {x}"),
+        };
+        direction = match outputs.next().unwrap().unwrap() {
+            0 => turn_left(&direction),
+            1 => turn_right(&direction),
+            x => panic!("bad direction output {x}"),
         };
         panels.insert(coord, color);
         coord = move_forward(coord, &direction);
@@ -0,0 +1,116 @@
+//! Compares starting programs from a fresh copy of their memory image with
+//! cloning an already loaded [`Program`], on day02 and day07 style workloads.
+//! As a baseline, the cost of the clones alone is compared with cloning a
+//! plain `Vec<i64>` memory, which is what cloning a program used to do.
+//!
+//! Run with `cargo bench -p intcode`.
+
+use std::{hint::black_box, time::Instant};
+
+use intcode::Program;
+use itertools::Itertools;
+
+fn bench(name: &str, iterations: u32, mut f: impl FnMut()) {
+    let start = Instant::now();
+    for _ in 0..iterations {
+        f();
+    }
+    let elapsed = start.elapsed();
+    println!("{name:<24} {:>12.2?}/iter", elapsed / iterations);
+}
+
+/// A day02 style program: a chain of additions and multiplications into
+/// address 0, starting from the noun and verb at addresses 1 and 2.
+fn day02_image() -> Vec<i64> {
+    let ops = 40;
+    let data = 4 * ops + 5;
+    let mut memory = vec![1, 0, 0, 0];
+    for i in 0..ops {
+        let opcode = if i % 3 == 0 { 2 } else { 1 };
+        memory.extend([opcode, 0, data + i % 3, 0]);
+    }
+    memory.push(99);
+    memory.extend([1, 2, 3]);
+    memory
+}
+
+fn day02(start: impl Fn() -> Program) {
+    for noun in 0..=99 {
+        for verb in 0..=99 {
+            let mut program = start();
+            program.poke(1, noun);
+            program.poke(2, verb);
+            program.run(None, Vec::new()).unwrap();
+            black_box(program.memory()[0]);
+        }
+    }
+}
+
+/// The day07 feedback loop example, padded to the size of a real puzzle
+/// input.
+fn day07_image() -> Vec<i64> {
+    let mut memory = vec![
+        3, 52, 1001, 52, -5, 52, 3, 53, 1, 52, 56, 54, 1007, 54, 5, 55, 1005, 55, 26, 1001, 54, -5,
+        54, 1105, 1, 12, 1, 53, 54, 53, 1008, 54, 0, 55, 1001, 55, 1, 55, 2, 53, 55, 53, 4, 53,
+        1001, 56, -1, 56, 1005, 56, 6, 99, 0, 0, 0, 0, 10,
+    ];
+    memory.resize(520, 0);
+    memory
+}
+
+fn day07(start: impl Fn() -> Program) {
+    for phases in (5..=9).permutations(5) {
+        let mut programs: Vec<_> = phases
+            .into_iter()
+            .map(|phase| {
+                let mut program = start();
+                program.run_with_input(phase, Vec::new()).unwrap();
+                program
+            })
+            .collect();
+        let mut signal = 0;
+        let mut halted = false;
+        while !halted {
+            for program in &mut programs {
+                let mut output = Vec::new();
+                halted = program.run_with_input(signal, &mut output).unwrap();
+                signal = output[0];
+            }
+        }
+        black_box(signal);
+    }
+}
+
+/// Make as many copies as the day02 search starts programs.
+fn clones<T>(clone: impl Fn() -> T) {
+    for _ in 0..100 * 100 {
+        black_box(clone());
+    }
+}
+
+fn main() {
+    let image = day02_image();
+    let program = Program::new(image.clone());
+    bench("day02 copy image", 20, || {
+        day02(|| Program::new(image.clone()))
+    });
+    bench("day02 clone program", 20, || day02(|| program.clone()));
+
+    // cloning matters more as images grow
+    let mut image = image;
+    image.resize(1 << 14, 0);
+    let program = Program::new(image.clone());
+    bench("day02 16k copy image", 1, || {
+        day02(|| Program::new(image.clone()))
+    });
+    bench("day02 16k clone program", 1, || day02(|| program.clone()));
+    bench("16k clones of vec", 1, || clones(|| image.clone()));
+    bench("16k clones of program", 1, || clones(|| program.clone()));
+
+    let image = day07_image();
+    let program = Program::new(image.clone());
+    bench("day07 copy image", 200, || {
+        day07(|| Program::new(image.clone()))
+    });
+    bench("day07 clone program", 200, || day07(|| program.clone()));
+}
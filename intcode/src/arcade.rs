@@ -0,0 +1,521 @@
+//! An arcade cabinet for the game of day 13 of Advent of Code 2019.
+//!
+//! The game outputs triples of `x`, `y` and a tile id to draw on the screen,
+//! except that `(-1, 0, score)` sets the score display. When it needs input
+//! it reads the joystick position: -1 for left, 0 for neutral and 1 for
+//! right. Writing 2 to address 0 lets it be played without quarters.
+
+use std::{
+    collections::HashMap,
+    error::Error,
+    fmt::{self, Write as _},
+    io::{self, BufRead, Write},
+};
+
+use crate::{IntcodeError, Program, StepResult, Word};
+
+/// The most columns and rows a screen is rendered with, so a game drawing
+/// far off the board can't make rendering run forever.
+const MAX_SIZE: i64 = 1024;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum Tile {
+    #[default]
+    Empty,
+    Wall,
+    Block,
+    Paddle,
+    Ball,
+}
+
+impl Tile {
+    #[must_use]
+    pub fn from_id(id: i64) -> Option<Tile> {
+        Some(match id {
+            0 => Tile::Empty,
+            1 => Tile::Wall,
+            2 => Tile::Block,
+            3 => Tile::Paddle,
+            4 => Tile::Ball,
+            _ => return None,
+        })
+    }
+
+    fn symbol(self) -> char {
+        match self {
+            Tile::Empty => ' ',
+            Tile::Wall => '#',
+            Tile::Block => '%',
+            Tile::Paddle => '=',
+            Tile::Ball => 'o',
+        }
+    }
+
+    /// The ANSI foreground color code to draw the tile in.
+    fn color(self) -> u8 {
+        match self {
+            Tile::Empty | Tile::Wall => 37,
+            Tile::Block => 33,
+            Tile::Paddle => 36,
+            Tile::Ball => 31,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Joystick {
+    Left,
+    #[default]
+    Neutral,
+    Right,
+}
+
+impl Joystick {
+    fn value(self) -> i64 {
+        match self {
+            Joystick::Left => -1,
+            Joystick::Neutral => 0,
+            Joystick::Right => 1,
+        }
+    }
+}
+
+/// An [`Arcade`] game failed.
+#[derive(Debug)]
+pub enum ArcadeError<W = i64> {
+    /// The game program failed, or drawing a frame failed.
+    Program(IntcodeError<W>),
+    /// The game drew a tile id that isn't a [`Tile`].
+    UnknownTile { ip: usize, id: W },
+    /// The game drew at a position too large for the screen.
+    InvalidPosition { ip: usize, x: W, y: W },
+}
+
+impl<W> From<IntcodeError<W>> for ArcadeError<W> {
+    fn from(error: IntcodeError<W>) -> ArcadeError<W> {
+        ArcadeError::Program(error)
+    }
+}
+
+impl<W: fmt::Display> fmt::Display for ArcadeError<W> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArcadeError::Program(error) => error.fmt(f),
+            ArcadeError::UnknownTile { ip, id } => {
+                write!(f, "unknown tile id {id} output at address {ip}")
+            }
+            ArcadeError::InvalidPosition { ip, x, y } => {
+                write!(f, "invalid position ({x}, {y}) output at address {ip}")
+            }
+        }
+    }
+}
+
+impl<W: fmt::Debug + fmt::Display + 'static> Error for ArcadeError<W> {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ArcadeError::Program(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+/// The tiles drawn so far and the score display.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Screen<W = i64> {
+    tiles: HashMap<(i64, i64), Tile>,
+    score: W,
+}
+
+impl<W: Word> Screen<W> {
+    #[must_use]
+    pub fn get(&self, x: i64, y: i64) -> Tile {
+        self.tiles.get(&(x, y)).copied().unwrap_or_default()
+    }
+
+    #[must_use]
+    pub fn score(&self) -> &W {
+        &self.score
+    }
+
+    /// The number of positions showing `tile`.
+    #[must_use]
+    pub fn count(&self, tile: Tile) -> usize {
+        self.tiles.values().filter(|&&t| t == tile).count()
+    }
+
+    /// A position showing `tile`, such as the ball.
+    #[must_use]
+    pub fn find(&self, tile: Tile) -> Option<(i64, i64)> {
+        self.tiles
+            .iter()
+            .find(|&(_, &t)| t == tile)
+            .map(|(&position, _)| position)
+    }
+
+    /// The screen as lines of text, followed by the score. The lines cover
+    /// the tiles drawn so far, up to 1024 columns and rows from the top left.
+    #[must_use]
+    pub fn render(&self) -> String {
+        self.draw(|text, tile| text.push(tile.symbol()))
+    }
+
+    /// The screen in color with ANSI escapes, starting by moving the cursor
+    /// to the top left corner so each frame overwrites the last.
+    #[must_use]
+    pub fn render_ansi(&self) -> String {
+        let mut text = self.draw(|text, tile| {
+            write!(text, "\x1b[{}m{}", tile.color(), tile.symbol()).unwrap();
+        });
+        text.insert_str(0, "\x1b[H\x1b[2J");
+        text.push_str("\x1b[0m");
+        text
+    }
+
+    fn draw(&self, mut draw_tile: impl FnMut(&mut String, Tile)) -> String {
+        let mut text = String::new();
+        let xs = self.tiles.keys().map(|&(x, _)| x);
+        let ys = self.tiles.keys().map(|&(_, y)| y);
+        if let (Some(left), Some(right), Some(top), Some(bottom)) =
+            (xs.clone().min(), xs.max(), ys.clone().min(), ys.max())
+        {
+            let right = right.min(left.saturating_add(MAX_SIZE - 1));
+            let bottom = bottom.min(top.saturating_add(MAX_SIZE - 1));
+            for y in top..=bottom {
+                for x in left..=right {
+                    draw_tile(&mut text, self.get(x, y));
+                }
+                text.push('\n');
+            }
+        }
+        writeln!(text, "Score: {}", self.score).unwrap();
+        text
+    }
+}
+
+/// Chooses the joystick position each time the game asks for it.
+pub trait Player<W = i64> {
+    fn joystick(&mut self, screen: &Screen<W>) -> Joystick;
+}
+
+impl<W, F: FnMut(&Screen<W>) -> Joystick> Player<W> for F {
+    fn joystick(&mut self, screen: &Screen<W>) -> Joystick {
+        self(screen)
+    }
+}
+
+/// A bot that keeps the paddle under the ball.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FollowBall;
+
+impl<W: Word> Player<W> for FollowBall {
+    fn joystick(&mut self, screen: &Screen<W>) -> Joystick {
+        match (screen.find(Tile::Ball), screen.find(Tile::Paddle)) {
+            (Some((ball, _)), Some((paddle, _))) if ball < paddle => Joystick::Left,
+            (Some((ball, _)), Some((paddle, _))) if ball > paddle => Joystick::Right,
+            _ => Joystick::Neutral,
+        }
+    }
+}
+
+/// Reads a line per move: `a`, `h` or `,` for left, `d`, `l` or `.` for
+/// right, and anything else for neutral. Once the input ends the joystick
+/// stays neutral.
+pub struct Keyboard<R>(R);
+
+impl<R: BufRead> Keyboard<R> {
+    pub fn new(reader: R) -> Keyboard<R> {
+        Keyboard(reader)
+    }
+}
+
+impl<W, R: BufRead> Player<W> for Keyboard<R> {
+    fn joystick(&mut self, _screen: &Screen<W>) -> Joystick {
+        let mut line = String::new();
+        // a broken input is treated like the end of input
+        self.0.read_line(&mut line).unwrap_or_default();
+        match line.trim() {
+            "a" | "h" | "," => Joystick::Left,
+            "d" | "l" | "." => Joystick::Right,
+            _ => Joystick::Neutral,
+        }
+    }
+}
+
+/// A game program connected to a screen.
+pub struct Arcade<W = i64> {
+    program: Program<W>,
+    screen: Screen<W>,
+    /// Values of a triple the game is part way through outputting.
+    output: Vec<W>,
+    /// Whether the screen changed since the last frame was shown.
+    changed: bool,
+}
+
+impl<W: Word> Arcade<W> {
+    #[must_use]
+    pub fn new(program: Program<W>) -> Arcade<W> {
+        Arcade {
+            program,
+            screen: Screen::default(),
+            output: Vec::new(),
+            changed: false,
+        }
+    }
+
+    /// Patch the game to play without quarters.
+    pub fn free_play(&mut self) {
+        self.program.poke(0, W::from_i64(2));
+    }
+
+    #[must_use]
+    pub fn screen(&self) -> &Screen<W> {
+        &self.screen
+    }
+
+    #[must_use]
+    pub fn program(&self) -> &Program<W> {
+        &self.program
+    }
+
+    /// Run until the game needs joystick input or halts, drawing its
+    /// outputs. Returns true if it halted.
+    pub fn update(&mut self) -> Result<bool, ArcadeError<W>> {
+        loop {
+            match self.program.run_until_event()? {
+                StepResult::Output(value) => {
+                    self.output.push(value);
+                    if let [x, y, id] = &mut self.output[..] {
+                        let (x, y, id) = (std::mem::take(x), std::mem::take(y), std::mem::take(id));
+                        self.output.clear();
+                        self.draw(x, y, id)?;
+                    }
+                }
+                StepResult::NeedsInput => return Ok(false),
+                StepResult::Halted => return Ok(true),
+                StepResult::Continue => unreachable!(),
+            }
+        }
+    }
+
+    fn draw(&mut self, x: W, y: W, id: W) -> Result<(), ArcadeError<W>> {
+        let ip = self.program.ip();
+        let Some(position) = x.to_i64().zip(y.to_i64()) else {
+            return Err(ArcadeError::InvalidPosition { ip, x, y });
+        };
+        if position == (-1, 0) {
+            self.changed |= self.screen.score != id;
+            self.screen.score = id;
+            return Ok(());
+        }
+        let Some(tile) = id.to_i64().and_then(Tile::from_id) else {
+            return Err(ArcadeError::UnknownTile { ip, id });
+        };
+        self.changed |= self.screen.tiles.insert(position, tile) != Some(tile);
+        Ok(())
+    }
+
+    /// Play until the game halts, and return the final score.
+    pub fn play<P: Player<W>>(&mut self, player: &mut P) -> Result<W, ArcadeError<W>> {
+        self.run(player, None::<io::Sink>)
+    }
+
+    /// Play like [`Arcade::play`], drawing a frame to `display` with
+    /// [`Screen::render_ansi`] whenever the screen or score changes.
+    pub fn play_rendered<P, T>(&mut self, player: &mut P, display: T) -> Result<W, ArcadeError<W>>
+    where
+        P: Player<W>,
+        T: Write,
+    {
+        self.run(player, Some(display))
+    }
+
+    fn run<P, T>(&mut self, player: &mut P, mut display: Option<T>) -> Result<W, ArcadeError<W>>
+    where
+        P: Player<W>,
+        T: Write,
+    {
+        loop {
+            let halted = self.update()?;
+            if let Some(display) = display.as_mut().filter(|_| self.changed) {
+                self.changed = false;
+                display
+                    .write_all(self.screen.render_ansi().as_bytes())
+                    .and_then(|()| display.flush())
+                    .map_err(|source| IntcodeError::Output {
+                        ip: self.program.ip(),
+                        source,
+                    })?;
+            }
+            if halted {
+                return Ok(self.screen.score.clone());
+            }
+            let joystick = player.joystick(&self.screen);
+            self.program.push_input(W::from_i64(joystick.value()));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{asm, BigInt};
+
+    /// A game won by moving the paddle under the ball, which needs free
+    /// play: with 2 at address 0 the first instruction multiplies instead of
+    /// adding.
+    fn game() -> Arcade {
+        let memory = asm::assemble(
+            "
+                    ADD [two], [one], [credits]
+                    EQ [credits], #2, [tmp]
+                    JT [tmp], #play
+                    OUT #-1
+                    OUT #0
+                    OUT #0
+                    HALT
+            play:   OUT #1
+                    OUT #0
+                    OUT #2
+                    OUT #3
+                    OUT #1
+                    OUT #4
+                    OUT #0
+                    OUT #1
+                    OUT #1
+            draw:   OUT [x]
+                    OUT #2
+                    OUT #3
+                    EQ [x], #3, [tmp]
+                    JT [tmp], #win
+                    IN [move]
+                    OUT [x]
+                    OUT #2
+                    OUT #0
+                    ADD [x], [move], [x]
+                    JT #1, #draw
+            win:    OUT #1
+                    OUT #0
+                    OUT #0
+                    OUT #-1
+                    OUT #0
+                    OUT #1000
+                    HALT
+            two:    .data 2
+            one:    .data 1
+            credits: .data 0
+            tmp:    .data 0
+            move:   .data 0
+            x:      .data 0
+            ",
+        )
+        .unwrap();
+        Arcade::new(Program::new(memory))
+    }
+
+    #[test]
+    fn screen() {
+        let mut arcade = game();
+        arcade.free_play();
+        assert!(!arcade.update().unwrap());
+        let screen = arcade.screen();
+        assert_eq!(screen.get(1, 0), Tile::Block);
+        assert_eq!(screen.count(Tile::Block), 1);
+        assert_eq!(screen.find(Tile::Paddle), Some((0, 2)));
+        assert_eq!(screen.render(), " %  \n#  o\n=   \nScore: 0\n");
+        let ansi = screen.render_ansi();
+        assert!(ansi.starts_with("\x1b[H\x1b[2J\x1b[37m "));
+        assert!(ansi.contains("\x1b[31mo"));
+    }
+
+    #[test]
+    fn players() {
+        // without free play the game ends at once
+        let mut arcade = game();
+        assert_eq!(arcade.play(&mut FollowBall).unwrap(), 0);
+        assert!(arcade.screen().find(Tile::Ball).is_none());
+
+        let mut arcade = game();
+        arcade.free_play();
+        let mut frames = Vec::new();
+        assert_eq!(
+            arcade.play_rendered(&mut FollowBall, &mut frames).unwrap(),
+            1000
+        );
+        assert_eq!(arcade.screen().count(Tile::Block), 0);
+        let frames = String::from_utf8(frames).unwrap();
+        assert_eq!(frames.matches("\x1b[H").count(), 4);
+
+        // wrong moves are undone before the paddle reaches the ball
+        let mut arcade = game();
+        arcade.free_play();
+        let mut keyboard = Keyboard::new("d\n.\na\nx\nl\nd\nd\n".as_bytes());
+        assert_eq!(arcade.play(&mut keyboard).unwrap(), 1000);
+
+        let mut moves = 0;
+        let mut arcade = game();
+        arcade.free_play();
+        let mut right = |_: &Screen| {
+            moves += 1;
+            Joystick::Right
+        };
+        assert_eq!(arcade.play(&mut right).unwrap(), 1000);
+        assert_eq!(moves, 3);
+    }
+
+    /// A game that outputs `values` and halts.
+    fn outputs<W: Word>(values: &[W]) -> Arcade<W> {
+        let mut memory = Vec::new();
+        for value in values {
+            memory.extend([W::from_i64(104), value.clone()]);
+        }
+        memory.push(W::from_i64(99));
+        Arcade::new(Program::from(memory))
+    }
+
+    #[test]
+    fn frames_on_change() {
+        // the wall is drawn again after the move, which needs no new frame
+        let mut arcade = Arcade::new(Program::new(vec![
+            104, 0, 104, 0, 104, 1, 3, 20, 104, 0, 104, 0, 104, 1, 99,
+        ]));
+        let mut frames = Vec::new();
+        assert_eq!(
+            arcade.play_rendered(&mut FollowBall, &mut frames).unwrap(),
+            0
+        );
+        let frames = String::from_utf8(frames).unwrap();
+        assert_eq!(frames.matches("\x1b[H").count(), 1);
+    }
+
+    #[test]
+    fn bounds() {
+        // negative positions are drawn
+        let mut arcade = outputs::<i64>(&[-1, -1, 1, 1, 0, 4]);
+        assert!(arcade.update().unwrap());
+        assert_eq!(arcade.screen().render(), "#  \n  o\nScore: 0\n");
+
+        // a tile far off the board doesn't make rendering run forever
+        let mut arcade = outputs::<i64>(&[0, 0, 1, 1 << 40, 0, 2]);
+        assert!(arcade.update().unwrap());
+        let render = arcade.screen().render();
+        assert_eq!(render.lines().next().unwrap().len(), 1024);
+    }
+
+    #[test]
+    fn errors() {
+        let err = outputs::<i64>(&[0, 0, 7]).update().unwrap_err();
+        assert!(matches!(err, ArcadeError::UnknownTile { id: 7, .. }));
+        assert_eq!(err.to_string(), "unknown tile id 7 output at address 6");
+
+        let huge: BigInt = "100000000000000000000".parse().unwrap();
+        let err = outputs(&[huge.clone(), BigInt::from(0), BigInt::from(1)])
+            .update()
+            .unwrap_err();
+        assert!(matches!(err, ArcadeError::InvalidPosition { .. }));
+
+        // scores aren't limited to an i64
+        let mut arcade = outputs(&[BigInt::from(-1), BigInt::from(0), huge.clone()]);
+        assert_eq!(arcade.play(&mut FollowBall).unwrap(), huge);
+    }
+}
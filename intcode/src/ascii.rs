@@ -0,0 +1,206 @@
+//! Text conversations with programs that use ASCII codes for input and
+//! output.
+//!
+//! Text is sent as one input per character code, and outputs from 0 to 127
+//! are read back as characters. Any other output, such as a large number
+//! giving a puzzle answer, is kept separately.
+
+use std::io::{BufRead, Write};
+
+use crate::{IntcodeError, Program, StepResult, Word};
+
+/// The output of an [`Ascii`] program up to when it stopped.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Reply<W = i64> {
+    pub text: String,
+    /// Outputs that aren't ASCII codes, in order.
+    pub values: Vec<W>,
+    /// Whether the program halted, rather than waiting for input.
+    pub halted: bool,
+}
+
+/// A program that talks in ASCII.
+pub struct Ascii<W = i64> {
+    program: Program<W>,
+}
+
+impl<W: Word> Ascii<W> {
+    #[must_use]
+    pub fn new(program: Program<W>) -> Ascii<W> {
+        Ascii { program }
+    }
+
+    #[must_use]
+    pub fn program(&self) -> &Program<W> {
+        &self.program
+    }
+
+    pub fn program_mut(&mut self) -> &mut Program<W> {
+        &mut self.program
+    }
+
+    #[must_use]
+    pub fn into_inner(self) -> Program<W> {
+        self.program
+    }
+
+    /// Queue the character codes of `text` as input. Characters outside
+    /// ASCII are sent as their Unicode code points.
+    pub fn send(&mut self, text: &str) {
+        for c in text.chars() {
+            self.program.push_input(W::from_i64(u32::from(c).into()));
+        }
+    }
+
+    /// Queue a line of input, adding the newline.
+    pub fn send_line(&mut self, line: &str) {
+        self.send(line);
+        self.send("\n");
+    }
+
+    /// Run until the program halts or needs more input than has been sent,
+    /// and return what it output.
+    pub fn run(&mut self) -> Result<Reply<W>, IntcodeError<W>> {
+        let mut reply = Reply::default();
+        loop {
+            match self.program.run_until_event()? {
+                StepResult::Output(value) => match to_ascii(&value) {
+                    Some(c) => reply.text.push(c),
+                    None => reply.values.push(value),
+                },
+                StepResult::NeedsInput => return Ok(reply),
+                StepResult::Halted => {
+                    reply.halted = true;
+                    return Ok(reply);
+                }
+                StepResult::Continue => unreachable!(),
+            }
+        }
+    }
+
+    /// Send `text`, then run like [`Ascii::run`].
+    pub fn converse(&mut self, text: &str) -> Result<Reply<W>, IntcodeError<W>> {
+        self.send(text);
+        self.run()
+    }
+
+    /// Run interactively until the program halts: text output is written to
+    /// `writer`, other outputs are written on lines of their own, and each
+    /// time the program needs input a line is read from `reader`.
+    pub fn interact<R, T>(&mut self, mut reader: R, mut writer: T) -> Result<(), IntcodeError<W>>
+    where
+        R: BufRead,
+        T: Write,
+    {
+        loop {
+            let reply = self.run()?;
+            let ip = self.program.ip();
+            write_reply(&mut writer, &reply)
+                .map_err(|source| IntcodeError::Output { ip, source })?;
+            if reply.halted {
+                return Ok(());
+            }
+
+            let mut line = String::new();
+            let read = reader
+                .read_line(&mut line)
+                .map_err(|source| IntcodeError::Input { ip, source })?;
+            if read == 0 {
+                return Err(IntcodeError::MissingInput { ip });
+            }
+            self.send_line(line.trim_end_matches(['\n', '\r']));
+        }
+    }
+}
+
+fn to_ascii<W: Word>(value: &W) -> Option<char> {
+    value
+        .to_i64()
+        .and_then(|code| u8::try_from(code).ok())
+        .filter(u8::is_ascii)
+        .map(char::from)
+}
+
+fn write_reply<W: Word, T: Write>(writer: &mut T, reply: &Reply<W>) -> std::io::Result<()> {
+    write!(writer, "{}", reply.text)?;
+    // start values on a new line when they follow text in the same reply
+    if !reply.values.is_empty() && !reply.text.is_empty() && !reply.text.ends_with('\n') {
+        writeln!(writer)?;
+    }
+    for value in &reply.values {
+        writeln!(writer, "{value}")?;
+    }
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm;
+
+    /// Echo each line in upper case, halting with the number of lines on an
+    /// empty one.
+    fn shout() -> Ascii {
+        let memory = asm::assemble(
+            "
+            start:  OUT #62
+                    OUT #32
+            loop:   IN [c]
+                    EQ [c], #10, [tmp]
+                    JT [tmp], #eol
+                    LT [c], #97, [tmp]
+                    JT [tmp], #out
+                    ADD [c], #-32, [c]
+            out:    OUT [c]
+                    ADD #1, #0, [used]
+                    JT #1, #loop
+            eol:    JF [used], #end
+                    OUT #10
+                    ADD [lines], #1, [lines]
+                    ADD #0, #0, [used]
+                    JT #1, #start
+            end:    OUT [lines]
+                    HALT
+            c:      .data 0
+            tmp:    .data 0
+            used:   .data 0
+            lines:  .data 1000
+            ",
+        )
+        .unwrap();
+        Ascii::new(Program::new(memory))
+    }
+
+    #[test]
+    fn conversation() {
+        let mut ascii = shout();
+        let reply = ascii.run().unwrap();
+        assert_eq!(reply.text, "> ");
+        assert!(reply.values.is_empty() && !reply.halted);
+
+        let reply = ascii.converse("hello\nWorld 2\n").unwrap();
+        assert_eq!(reply.text, "HELLO\n> WORLD 2\n> ");
+
+        ascii.send_line("");
+        assert_eq!(
+            ascii.run().unwrap(),
+            Reply {
+                text: String::new(),
+                values: vec![1002],
+                halted: true,
+            }
+        );
+    }
+
+    #[test]
+    fn interactive() {
+        let mut output = Vec::new();
+        shout()
+            .interact("abc\r\n\n".as_bytes(), &mut output)
+            .unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "> ABC\n> 1001\n");
+
+        let err = shout().interact("abc".as_bytes(), Vec::new()).unwrap_err();
+        assert!(matches!(err, IntcodeError::MissingInput { .. }));
+    }
+}
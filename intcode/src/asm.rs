@@ -0,0 +1,492 @@
+//! An assembler for Intcode, accepting the syntax produced by
+//! [`disasm`](crate::disasm):
+//!
+//! ```text
+//! ; add one to each input until an input of zero
+//! start:  IN [x]
+//!         JF [x], #end
+//!         ADD [x], #1, [x]
+//!         OUT [x]
+//!         JT #1, #start
+//! end:    HALT
+//! x:      .data 0
+//! ```
+//!
+//! Operands are `#expr` for immediate mode, `[expr]` for position mode and
+//! `[rb+expr]` for relative mode, where an expression is a sum of numbers and
+//! labels. `.data` emits its comma separated expressions as raw words.
+
+use std::{collections::HashMap, error::Error, fmt};
+
+use crate::{Instruction, Opcode, ParamMode};
+
+/// An assembly error at a 1-based line and column.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AsmError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl Error for AsmError {}
+
+#[derive(Debug)]
+enum Term {
+    Number { value: i64, column: usize },
+    Label { name: String, column: usize },
+}
+
+/// A sum of signed terms. The sign of a number is part of its value.
+#[derive(Debug)]
+struct Expr(Vec<(bool, Term)>);
+
+#[derive(Debug)]
+struct Word {
+    line: usize,
+    expr: Expr,
+}
+
+struct LineParser<'a> {
+    line: usize,
+    text: &'a str,
+    pos: usize,
+}
+
+impl LineParser<'_> {
+    fn error<T>(&self, column: usize, message: impl Into<String>) -> Result<T, AsmError> {
+        Err(AsmError {
+            line: self.line,
+            column,
+            message: message.into(),
+        })
+    }
+
+    fn column(&self) -> usize {
+        self.text[..self.pos].chars().count() + 1
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = &self.text[self.pos..];
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.skip_whitespace();
+        self.text[self.pos..].chars().next()
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.pos += c.len_utf8();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), AsmError> {
+        if self.eat(c) {
+            Ok(())
+        } else {
+            self.error(self.column(), format!("expected '{c}'"))
+        }
+    }
+
+    fn at_end(&mut self) -> bool {
+        self.peek().is_none()
+    }
+
+    fn take_while(&mut self, f: impl Fn(char) -> bool) -> &str {
+        self.skip_whitespace();
+        let start = self.pos;
+        let rest = &self.text[start..];
+        let len = rest.find(|c| !f(c)).unwrap_or(rest.len());
+        self.pos += len;
+        &self.text[start..self.pos]
+    }
+
+    fn ident(&mut self) -> Option<String> {
+        match self.peek() {
+            Some(c) if c.is_ascii_alphabetic() || c == '_' || c == '.' => {
+                let ident = self.take_while(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.');
+                Some(ident.to_string())
+            }
+            _ => None,
+        }
+    }
+
+    /// Parse a term, following a minus sign if `negative`.
+    fn term(&mut self, negative: bool) -> Result<(bool, Term), AsmError> {
+        let column = self.column_after_whitespace();
+        match self.peek() {
+            Some(c) if c.is_ascii_digit() => {
+                let digits = self.take_while(|c| c.is_ascii_digit());
+                // parse the sign with the digits so i64::MIN can be written
+                let number = if negative {
+                    format!("-{digits}")
+                } else {
+                    digits.to_string()
+                };
+                match number.parse() {
+                    Ok(value) => Ok((false, Term::Number { value, column })),
+                    Err(_) => self.error(column, format!("number '{number}' is too large")),
+                }
+            }
+            Some(c) if c.is_ascii_alphabetic() || c == '_' => {
+                let name = self.ident().unwrap();
+                if name.eq_ignore_ascii_case("rb") {
+                    return self.error(column, "'rb' is only allowed as '[rb+n]'");
+                }
+                Ok((negative, Term::Label { name, column }))
+            }
+            _ => self.error(column, "expected a number or label"),
+        }
+    }
+
+    fn expr(&mut self) -> Result<Expr, AsmError> {
+        let mut terms = Vec::new();
+        let mut negative = self.eat('-');
+        loop {
+            terms.push(self.term(negative)?);
+            if self.eat('+') {
+                negative = false;
+            } else if self.eat('-') {
+                negative = true;
+            } else {
+                return Ok(Expr(terms));
+            }
+        }
+    }
+
+    fn column_after_whitespace(&mut self) -> usize {
+        self.skip_whitespace();
+        self.column()
+    }
+
+    fn operand(&mut self) -> Result<(ParamMode, Expr), AsmError> {
+        if self.eat('#') {
+            return Ok((ParamMode::Immediate, self.expr()?));
+        }
+        let column = self.column_after_whitespace();
+        if !self.eat('[') {
+            return self.error(column, "expected an operand like '#5', '[12]' or '[rb+3]'");
+        }
+        let start = self.pos;
+        let operand = match self.ident() {
+            Some(ident) if ident.eq_ignore_ascii_case("rb") => {
+                let expr = if self.eat('+') || self.peek() == Some('-') {
+                    self.expr()?
+                } else {
+                    Expr(vec![(false, Term::Number { value: 0, column })])
+                };
+                (ParamMode::Relative, expr)
+            }
+            _ => {
+                self.pos = start;
+                (ParamMode::Position, self.expr()?)
+            }
+        };
+        self.expect(']')?;
+        Ok(operand)
+    }
+
+    fn list<T>(
+        &mut self,
+        f: impl Fn(&mut Self) -> Result<T, AsmError>,
+    ) -> Result<Vec<T>, AsmError> {
+        let mut items = Vec::new();
+        if self.at_end() {
+            return Ok(items);
+        }
+        loop {
+            items.push(f(self)?);
+            if !self.eat(',') {
+                break;
+            }
+        }
+        if !self.at_end() {
+            return self.error(self.column(), "unexpected text");
+        }
+        Ok(items)
+    }
+}
+
+fn resolve(expr: &Expr, line: usize, labels: &HashMap<String, usize>) -> Result<i64, AsmError> {
+    let mut value: i64 = 0;
+    for (negative, term) in &expr.0 {
+        let (term, column) = match term {
+            Term::Number { value, column } => (*value, *column),
+            Term::Label { name, column } => match labels.get(name) {
+                Some(&addr) => (addr as i64, *column),
+                None => {
+                    return Err(AsmError {
+                        line,
+                        column: *column,
+                        message: format!("undefined label '{name}'"),
+                    })
+                }
+            },
+        };
+        let sum = if *negative {
+            value.checked_sub(term)
+        } else {
+            value.checked_add(term)
+        };
+        value = sum.ok_or_else(|| AsmError {
+            line,
+            column,
+            message: "value is too large".to_string(),
+        })?;
+    }
+    Ok(value)
+}
+
+/// Assemble source text into a memory image ready for
+/// [`Program::new`](crate::Program::new).
+pub fn assemble(source: &str) -> Result<Vec<i64>, AsmError> {
+    let mut words = Vec::new();
+    let mut labels = HashMap::new();
+
+    for (i, text) in source.lines().enumerate() {
+        let text = text.split(';').next().unwrap();
+        let mut parser = LineParser {
+            line: i + 1,
+            text,
+            pos: 0,
+        };
+
+        let mut column = parser.column_after_whitespace();
+        let mut name = parser.ident();
+        if name.is_some() && parser.eat(':') {
+            let label = name.take().unwrap();
+            if label.starts_with('.') || label.eq_ignore_ascii_case("rb") {
+                return parser.error(column, format!("invalid label name '{label}'"));
+            }
+            if labels.insert(label.clone(), words.len()).is_some() {
+                return parser.error(column, format!("duplicate label '{label}'"));
+            }
+            column = parser.column_after_whitespace();
+            name = parser.ident();
+        }
+        let Some(name) = name else {
+            if parser.at_end() {
+                continue;
+            }
+            return parser.error(parser.column(), "expected a label, mnemonic or directive");
+        };
+
+        if name.eq_ignore_ascii_case(".data") {
+            let values = parser.list(LineParser::expr)?;
+            if values.is_empty() {
+                return parser.error(parser.column(), "expected data after '.data'");
+            }
+            words.extend(values.into_iter().map(|expr| Word { line: i + 1, expr }));
+            continue;
+        }
+
+        let Some(opcode) = Opcode::from_mnemonic(&name) else {
+            return parser.error(column, format!("unknown mnemonic '{name}'"));
+        };
+        let operands = parser.list(|parser| {
+            let column = parser.column_after_whitespace();
+            parser.operand().map(|operand| (column, operand))
+        })?;
+        if operands.len() != opcode.param_count() {
+            return parser.error(
+                column,
+                format!(
+                    "{} takes {} operands but {} were given",
+                    opcode.mnemonic(),
+                    opcode.param_count(),
+                    operands.len()
+                ),
+            );
+        }
+        let mut modes = [ParamMode::Position; 3];
+        for (index, (column, (mode, _))) in operands.iter().enumerate() {
+            if *mode == ParamMode::Immediate && opcode.write_param() == Some(index) {
+                return parser.error(*column, "cannot write to an immediate operand");
+            }
+            modes[index] = *mode;
+        }
+        let instruction = Instruction { opcode, modes };
+        words.push(Word {
+            line: i + 1,
+            expr: Expr(vec![(
+                false,
+                Term::Number {
+                    value: instruction.encode(),
+                    column,
+                },
+            )]),
+        });
+        words.extend(
+            operands
+                .into_iter()
+                .map(|(_, (_, expr))| Word { line: i + 1, expr }),
+        );
+    }
+
+    words
+        .iter()
+        .map(|word| resolve(&word.expr, word.line, &labels))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+
+    use super::*;
+    use crate::{disasm, Program};
+
+    #[test]
+    fn assemble_program() {
+        let source = "
+            ; add one to each input until an input of zero
+            start:  IN [x]
+                    JF [x], #end
+                    add [x], #1, [x]   ; mnemonics are case insensitive
+                    OUT [x]
+                    JT #1, #start
+            end:    HALT
+            x:      .data 0
+        ";
+        let memory = assemble(source).unwrap();
+        assert_eq!(
+            memory,
+            [3, 15, 1006, 15, 14, 1001, 15, 1, 15, 4, 15, 1105, 1, 0, 99, 0]
+        );
+        let mut output = Vec::new();
+        Program::new(memory)
+            .run(VecDeque::from([4, 9, 0]), &mut output)
+            .unwrap();
+        assert_eq!(output, [5, 10]);
+    }
+
+    #[test]
+    fn operands() {
+        let memory =
+            assemble("ARB #-3\nADD [rb+2], [rb-1], [rb]\nOUT #a+2\na: .data 7, a-1").unwrap();
+        assert_eq!(memory, [109, -3, 22201, 2, -1, 0, 104, 10, 7, 7]);
+        assert_eq!(
+            assemble(".data -9223372036854775808, 9223372036854775807").unwrap(),
+            [i64::MIN, i64::MAX]
+        );
+    }
+
+    #[test]
+    fn round_trip() {
+        let memory = vec![
+            3, 21, 1008, 21, 8, 20, 1005, 20, 22, 107, 8, 21, 20, 1006, 20, 31, 1106, 0, 36, 98, 0,
+            0, 1002, 21, 125, 20, 4, 20, 1105, 1, 46, 104, 999, 1105, 1, 46, 1101, 1000, 1, 20, 4,
+            20, 1105, 1, 46, 98, 99,
+        ];
+        let source: Vec<_> = disasm::disassemble(&memory)
+            .iter()
+            .map(disasm::Line::text)
+            .collect();
+        assert_eq!(assemble(&source.join("\n")).unwrap(), memory);
+    }
+
+    #[test]
+    fn day_examples_round_trip() {
+        let examples: [&[i64]; 8] = [
+            // day 2
+            &[1, 9, 10, 3, 2, 3, 11, 0, 99, 30, 40, 50],
+            // day 5
+            &[3, 9, 8, 9, 10, 9, 4, 9, 99, -1, 8],
+            &[3, 3, 1107, -1, 8, 3, 4, 3, 99],
+            &[3, 12, 6, 12, 15, 1, 13, 14, 13, 4, 13, 99, -1, 0, 1, 9],
+            // day 7
+            &[
+                3, 26, 1001, 26, -4, 26, 3, 27, 1002, 27, 2, 27, 1, 27, 26, 27, 4, 27, 1001, 28,
+                -1, 28, 1005, 28, 6, 99, 0, 0, 5,
+            ],
+            // day 9
+            &[
+                109, 1, 204, -1, 1001, 100, 1, 100, 1008, 100, 16, 101, 1006, 101, 0, 99,
+            ],
+            &[1102, 34915192, 34915192, 7, 4, 7, 99, 0],
+            &[104, 1125899906842624, 99],
+        ];
+        for memory in examples {
+            let source: Vec<_> = disasm::disassemble(memory)
+                .iter()
+                .map(disasm::Line::text)
+                .collect();
+            assert_eq!(assemble(&source.join("\n")).unwrap(), memory);
+        }
+
+        // words that decode but don't reassemble are kept as data
+        let memory = [10099, 11101, 1, 2, 3, 103, 5];
+        let source: Vec<_> = disasm::disassemble(&memory)
+            .iter()
+            .map(disasm::Line::text)
+            .collect();
+        assert_eq!(assemble(&source.join("\n")).unwrap(), memory);
+    }
+
+    #[test]
+    fn errors() {
+        let error = |source| {
+            let AsmError {
+                line,
+                column,
+                message,
+            } = assemble(source).unwrap_err();
+            (line, column, message)
+        };
+        assert_eq!(
+            error("HALT\n  FOO #1"),
+            (2, 3, "unknown mnemonic 'FOO'".to_string())
+        );
+        assert_eq!(
+            error("ADD #1, #2, #3"),
+            (1, 13, "cannot write to an immediate operand".to_string())
+        );
+        assert_eq!(
+            error("OUT [missing]"),
+            (1, 6, "undefined label 'missing'".to_string())
+        );
+        assert_eq!(
+            error("a: HALT\na: HALT"),
+            (2, 1, "duplicate label 'a'".to_string())
+        );
+        assert_eq!(
+            error("OUT #1, #2"),
+            (1, 1, "OUT takes 1 operands but 2 were given".to_string())
+        );
+        assert_eq!(error("OUT [5"), (1, 7, "expected ']'".to_string()));
+        assert_eq!(
+            error(".data 9223372036854775808"),
+            (
+                1,
+                7,
+                "number '9223372036854775808' is too large".to_string()
+            )
+        );
+        assert_eq!(
+            error("HALT\na: .data a+9223372036854775807"),
+            (2, 12, "value is too large".to_string())
+        );
+        assert_eq!(
+            error(".data -9223372036854775808-1"),
+            (1, 28, "value is too large".to_string())
+        );
+        assert_eq!(
+            error("OUT 5"),
+            (
+                1,
+                5,
+                "expected an operand like '#5', '[12]' or '[rb+3]'".to_string()
+            )
+        );
+    }
+}
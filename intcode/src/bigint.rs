@@ -0,0 +1,272 @@
+use std::{
+    cmp::Ordering,
+    fmt,
+    ops::{Add, Mul, Neg, Sub},
+    str::FromStr,
+};
+
+/// Digits are stored in base 10^9 so formatting and parsing are simple.
+const BASE: u64 = 1_000_000_000;
+
+/// An arbitrary precision signed integer.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct BigInt {
+    negative: bool,
+    /// Base 10^9 digits, least significant first, with no leading zeros.
+    /// Zero has no digits and is never negative.
+    digits: Vec<u32>,
+}
+
+fn cmp_digits(a: &[u32], b: &[u32]) -> Ordering {
+    a.len()
+        .cmp(&b.len())
+        .then_with(|| a.iter().rev().cmp(b.iter().rev()))
+}
+
+fn add_digits(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut digits = Vec::with_capacity(a.len().max(b.len()) + 1);
+    let mut carry = 0;
+    for i in 0..a.len().max(b.len()) {
+        let sum = u64::from(*a.get(i).unwrap_or(&0)) + u64::from(*b.get(i).unwrap_or(&0)) + carry;
+        digits.push((sum % BASE) as u32);
+        carry = sum / BASE;
+    }
+    if carry > 0 {
+        digits.push(carry as u32);
+    }
+    digits
+}
+
+/// `a - b` where `a >= b`.
+fn sub_digits(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut digits = Vec::with_capacity(a.len());
+    let mut borrow = 0;
+    for (i, &digit) in a.iter().enumerate() {
+        let subtrahend = i64::from(*b.get(i).unwrap_or(&0)) + borrow;
+        let mut difference = i64::from(digit) - subtrahend;
+        borrow = 0;
+        if difference < 0 {
+            difference += BASE as i64;
+            borrow = 1;
+        }
+        digits.push(difference as u32);
+    }
+    digits
+}
+
+fn mul_digits(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut digits = vec![0u64; a.len() + b.len()];
+    for (i, &x) in a.iter().enumerate() {
+        let mut carry = 0;
+        for (j, &y) in b.iter().enumerate() {
+            let product = digits[i + j] + u64::from(x) * u64::from(y) + carry;
+            digits[i + j] = product % BASE;
+            carry = product / BASE;
+        }
+        digits[i + b.len()] += carry;
+    }
+    digits.into_iter().map(|digit| digit as u32).collect()
+}
+
+impl BigInt {
+    fn new(negative: bool, mut digits: Vec<u32>) -> BigInt {
+        while digits.last() == Some(&0) {
+            digits.pop();
+        }
+        BigInt {
+            negative: negative && !digits.is_empty(),
+            digits,
+        }
+    }
+
+    #[must_use]
+    pub fn is_negative(&self) -> bool {
+        self.negative
+    }
+
+    /// The value as an `i64`, or `None` if it doesn't fit.
+    #[must_use]
+    pub fn to_i64(&self) -> Option<i64> {
+        let mut magnitude: i128 = 0;
+        for &digit in self.digits.iter().rev() {
+            magnitude = magnitude
+                .checked_mul(BASE.into())?
+                .checked_add(i128::from(digit))?;
+        }
+        let value = if self.negative { -magnitude } else { magnitude };
+        value.try_into().ok()
+    }
+}
+
+impl From<i64> for BigInt {
+    fn from(value: i64) -> BigInt {
+        let mut magnitude = value.unsigned_abs();
+        let mut digits = Vec::new();
+        while magnitude > 0 {
+            digits.push((magnitude % BASE) as u32);
+            magnitude /= BASE;
+        }
+        BigInt::new(value < 0, digits)
+    }
+}
+
+impl Ord for BigInt {
+    fn cmp(&self, other: &BigInt) -> Ordering {
+        match (self.negative, other.negative) {
+            (false, true) => Ordering::Greater,
+            (true, false) => Ordering::Less,
+            (false, false) => cmp_digits(&self.digits, &other.digits),
+            (true, true) => cmp_digits(&other.digits, &self.digits),
+        }
+    }
+}
+
+impl PartialOrd for BigInt {
+    fn partial_cmp(&self, other: &BigInt) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Neg for BigInt {
+    type Output = BigInt;
+
+    fn neg(self) -> BigInt {
+        BigInt::new(!self.negative, self.digits)
+    }
+}
+
+impl Add for BigInt {
+    type Output = BigInt;
+
+    fn add(self, rhs: BigInt) -> BigInt {
+        if self.negative == rhs.negative {
+            return BigInt::new(self.negative, add_digits(&self.digits, &rhs.digits));
+        }
+        match cmp_digits(&self.digits, &rhs.digits) {
+            Ordering::Less => BigInt::new(rhs.negative, sub_digits(&rhs.digits, &self.digits)),
+            _ => BigInt::new(self.negative, sub_digits(&self.digits, &rhs.digits)),
+        }
+    }
+}
+
+impl Sub for BigInt {
+    type Output = BigInt;
+
+    fn sub(self, rhs: BigInt) -> BigInt {
+        self + -rhs
+    }
+}
+
+impl Mul for BigInt {
+    type Output = BigInt;
+
+    fn mul(self, rhs: BigInt) -> BigInt {
+        BigInt::new(
+            self.negative != rhs.negative,
+            mul_digits(&self.digits, &rhs.digits),
+        )
+    }
+}
+
+impl fmt::Display for BigInt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Some((last, rest)) = self.digits.split_last() else {
+            return f.pad("0");
+        };
+        let mut text = String::new();
+        if self.negative {
+            text.push('-');
+        }
+        text.push_str(&last.to_string());
+        for digit in rest.iter().rev() {
+            text.push_str(&format!("{digit:09}"));
+        }
+        f.pad(&text)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseBigIntError;
+
+impl fmt::Display for ParseBigIntError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("invalid integer")
+    }
+}
+
+impl std::error::Error for ParseBigIntError {}
+
+impl FromStr for BigInt {
+    type Err = ParseBigIntError;
+
+    fn from_str(text: &str) -> Result<BigInt, ParseBigIntError> {
+        let (negative, decimal) = match text.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, text.strip_prefix('+').unwrap_or(text)),
+        };
+        if decimal.is_empty() || !decimal.bytes().all(|byte| byte.is_ascii_digit()) {
+            return Err(ParseBigIntError);
+        }
+        let digits = decimal
+            .as_bytes()
+            .rchunks(9)
+            .map(|chunk| std::str::from_utf8(chunk).unwrap().parse().unwrap())
+            .collect();
+        Ok(BigInt::new(negative, digits))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn big(text: &str) -> BigInt {
+        text.parse().unwrap()
+    }
+
+    #[test]
+    fn arithmetic() {
+        let a = big("123456789012345678901234567890");
+        let b = big("-987654321098765432109876543210");
+        assert_eq!(
+            (a.clone() + b.clone()).to_string(),
+            "-864197532086419753208641975320"
+        );
+        assert_eq!(
+            (a.clone() - b.clone()).to_string(),
+            "1111111110111111111011111111100"
+        );
+        assert_eq!(
+            (a.clone() * b.clone()).to_string(),
+            "-121932631137021795226185032733622923332237463801111263526900"
+        );
+        assert_eq!((a.clone() - a.clone()), BigInt::default());
+        assert!(!(a.clone() - a).is_negative());
+        assert!(b < BigInt::from(-1));
+        assert_eq!(
+            BigInt::from(-1_000_000_000) * BigInt::from(3),
+            big("-3000000000")
+        );
+    }
+
+    #[test]
+    fn conversions() {
+        for value in [0, 1, -1, 999_999_999, 1_000_000_000, i64::MAX, i64::MIN] {
+            let value_big = BigInt::from(value);
+            assert_eq!(value_big.to_i64(), Some(value));
+            assert_eq!(value_big.to_string(), value.to_string());
+            assert_eq!(big(&value.to_string()), value_big);
+        }
+        assert_eq!((BigInt::from(i64::MAX) + BigInt::from(1)).to_i64(), None);
+        assert_eq!(
+            big("170141183460469231731687303715884105728").to_i64(),
+            None
+        );
+        assert_eq!(big("+007"), BigInt::from(7));
+        assert_eq!(big("-0"), BigInt::default());
+        assert_eq!(format!("{:>4}", BigInt::from(-5)), "  -5");
+        for text in ["", "-", "1.5", "12a", " 1"] {
+            assert_eq!(text.parse::<BigInt>(), Err(ParseBigIntError));
+        }
+    }
+}
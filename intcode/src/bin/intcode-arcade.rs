@@ -0,0 +1,39 @@
+use std::{env, io, process};
+
+use intcode::{
+    arcade::{Arcade, FollowBall, Keyboard},
+    Program,
+};
+
+fn main() {
+    let args: Vec<_> = env::args().skip(1).collect();
+    let (path, bot) = match &args[..] {
+        [path] => (path, false),
+        [flag, path] if flag == "--bot" => (path, true),
+        _ => {
+            eprintln!("usage: intcode-arcade [--bot] <program file>");
+            process::exit(2);
+        }
+    };
+    let program = match Program::from_file(path) {
+        Ok(program) => program,
+        Err(err) => {
+            eprintln!("failed to read {path}: {err}");
+            process::exit(1);
+        }
+    };
+    let mut arcade = Arcade::new(program);
+    arcade.free_play();
+    let result = if bot {
+        arcade.play_rendered(&mut FollowBall, io::stdout())
+    } else {
+        arcade.play_rendered(&mut Keyboard::new(io::stdin().lock()), io::stdout())
+    };
+    match result {
+        Ok(score) => println!("final score: {score}"),
+        Err(err) => {
+            eprintln!("error: {err}");
+            process::exit(1);
+        }
+    }
+}
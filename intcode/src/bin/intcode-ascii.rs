@@ -0,0 +1,21 @@
+use std::{env, io, process};
+
+use intcode::{ascii::Ascii, Program};
+
+fn main() {
+    let Some(path) = env::args().nth(1) else {
+        eprintln!("usage: intcode-ascii <program file>");
+        process::exit(2);
+    };
+    let program = match Program::from_file(&path) {
+        Ok(program) => program,
+        Err(err) => {
+            eprintln!("failed to read {path}: {err}");
+            process::exit(1);
+        }
+    };
+    if let Err(err) = Ascii::new(program).interact(io::stdin().lock(), io::stdout()) {
+        eprintln!("error: {err}");
+        process::exit(1);
+    }
+}
@@ -0,0 +1,16 @@
+use std::{env, process};
+
+fn main() {
+    let Some(path) = env::args().nth(1) else {
+        eprintln!("usage: intcode-cfg <program file>");
+        process::exit(2);
+    };
+    let memory = match intcode::read_program_file(&path) {
+        Ok(memory) => memory,
+        Err(err) => {
+            eprintln!("failed to read {path}: {err}");
+            process::exit(1);
+        }
+    };
+    print!("{}", intcode::cfg::Cfg::build(&memory).to_dot(&memory));
+}
@@ -0,0 +1,37 @@
+use std::{
+    env,
+    io::{self, BufRead, Write},
+    process,
+};
+
+use intcode::{debugger::Debugger, Program};
+
+fn main() {
+    let Some(path) = env::args().nth(1) else {
+        eprintln!("usage: intcode-dbg <program file>");
+        process::exit(2);
+    };
+    let program = match Program::from_file(&path) {
+        Ok(program) => program,
+        Err(err) => {
+            eprintln!("failed to read {path}: {err}");
+            process::exit(1);
+        }
+    };
+    let mut debugger = Debugger::new(program);
+    println!("{}", debugger.execute("regs").unwrap());
+
+    let mut lines = io::stdin().lock().lines();
+    loop {
+        print!("(dbg) ");
+        io::stdout().flush().unwrap();
+        let Some(Ok(line)) = lines.next() else {
+            break;
+        };
+        match debugger.execute(&line) {
+            Some(text) if text.is_empty() => {}
+            Some(text) => println!("{}", text.trim_end()),
+            None => break,
+        }
+    }
+}
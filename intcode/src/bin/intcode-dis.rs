@@ -0,0 +1,18 @@
+use std::{env, process};
+
+fn main() {
+    let Some(path) = env::args().nth(1) else {
+        eprintln!("usage: intcode-dis <program file>");
+        process::exit(2);
+    };
+    let memory = match intcode::read_program_file(&path) {
+        Ok(memory) => memory,
+        Err(err) => {
+            eprintln!("failed to read {path}: {err}");
+            process::exit(1);
+        }
+    };
+    for line in intcode::disasm::disassemble(&memory) {
+        println!("{line}");
+    }
+}
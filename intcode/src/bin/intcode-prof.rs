@@ -0,0 +1,31 @@
+use std::{env, io, process};
+
+use intcode::{
+    io::{TextInput, TextOutput},
+    Program,
+};
+
+fn main() {
+    let Some(path) = env::args().nth(1) else {
+        eprintln!("usage: intcode-prof <program file>");
+        process::exit(2);
+    };
+    let mut program = match Program::from_file(&path) {
+        Ok(program) => program,
+        Err(err) => {
+            eprintln!("failed to read {path}: {err}");
+            process::exit(1);
+        }
+    };
+    program.start_profile();
+    let result = program.run(
+        TextInput::new(io::stdin().lock()),
+        TextOutput::new(io::stdout()),
+    );
+    let profile = program.take_profile().unwrap();
+    eprint!("{}", profile.report(program.memory(), 20));
+    if let Err(err) = result {
+        eprintln!("error: {err}");
+        process::exit(1);
+    }
+}
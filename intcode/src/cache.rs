@@ -0,0 +1,294 @@
+use std::sync::Arc;
+
+use crate::{Instruction, IntcodeError, Opcode, ParamMode, Program, StepResult, Word};
+
+/// Instructions at higher addresses are never cached.
+const MAX_CACHED_ADDR: usize = 1 << 20;
+
+/// A parameter with its mode already applied to the raw word.
+#[derive(Debug, Clone)]
+enum Operand<W> {
+    Immediate(W),
+    Position(usize),
+    Relative(W),
+}
+
+/// An instruction decoded along with its parameter words.
+#[derive(Debug, Clone)]
+struct Decoded<W> {
+    opcode: Opcode,
+    size: u8,
+    operands: [Operand<W>; 3],
+}
+
+/// Decoded instructions by address. An entry is removed whenever any word it
+/// was decoded from is written.
+#[derive(Debug, Clone)]
+pub(crate) struct DecodeCache<W> {
+    entries: Vec<Option<Decoded<W>>>,
+}
+
+impl<W> Default for DecodeCache<W> {
+    fn default() -> DecodeCache<W> {
+        DecodeCache {
+            entries: Vec::new(),
+        }
+    }
+}
+
+impl<W: Word> DecodeCache<W> {
+    fn get(&self, addr: usize) -> Option<&Decoded<W>> {
+        self.entries.get(addr)?.as_ref()
+    }
+
+    fn insert(&mut self, addr: usize, decoded: Decoded<W>) {
+        if addr >= self.entries.len() {
+            self.entries.resize(addr + 1, None);
+        }
+        self.entries[addr] = Some(decoded);
+    }
+
+    /// Whether a write to `addr` would change a cached instruction.
+    fn covers(&self, addr: usize) -> bool {
+        (addr.saturating_sub(3)..=addr).any(|start| {
+            self.get(start)
+                .is_some_and(|d| start + usize::from(d.size) > addr)
+        })
+    }
+
+    fn invalidate(&mut self, addr: usize) {
+        for start in addr.saturating_sub(3)..=addr {
+            if self
+                .get(start)
+                .is_some_and(|d| start + usize::from(d.size) > addr)
+            {
+                self.entries[start] = None;
+            }
+        }
+    }
+}
+
+impl<W: Word> Program<W> {
+    /// Decode each instruction once and reuse the decoded form until its
+    /// memory is written. Execution gives the same results either way.
+    ///
+    /// The cache is only used while no trace or history is being recorded.
+    pub fn enable_decode_cache(&mut self) {
+        if self.cache.is_none() {
+            self.cache = Some(Arc::default());
+        }
+    }
+
+    pub fn disable_decode_cache(&mut self) {
+        self.cache = None;
+    }
+
+    /// Forget cached instructions that a write to `addr` changes.
+    pub(crate) fn invalidate_cache(&mut self, addr: usize) {
+        if let Some(cache) = &mut self.cache {
+            // avoid copying a cache shared with a clone if nothing changes
+            if cache.covers(addr) {
+                Arc::make_mut(cache).invalidate(addr);
+            }
+        }
+    }
+
+    pub(crate) fn clear_cache(&mut self) {
+        if self.cache.is_some() {
+            self.cache = Some(Arc::default());
+        }
+    }
+
+    fn decode_cached(&mut self) -> Option<Decoded<W>> {
+        let cache = self.cache.as_mut()?;
+        if let Some(decoded) = cache.get(self.ip) {
+            return Some(decoded.clone());
+        }
+        if self.ip >= MAX_CACHED_ADDR {
+            return None;
+        }
+        let instruction = Instruction::decode(self.memory.get(self.ip).to_i64()?).ok()?;
+        let mut operands = [(); 3].map(|()| Operand::Immediate(W::default()));
+        for (i, &mode) in instruction.param_modes().iter().enumerate() {
+            let word = self.memory.get(self.ip + i + 1);
+            operands[i] = match mode {
+                ParamMode::Immediate => Operand::Immediate(word),
+                ParamMode::Position => Operand::Position(word.to_usize()?),
+                ParamMode::Relative => Operand::Relative(word),
+            };
+        }
+        let decoded = Decoded {
+            opcode: instruction.opcode,
+            size: instruction.size() as u8,
+            operands,
+        };
+        Arc::make_mut(cache).insert(self.ip, decoded.clone());
+        Some(decoded)
+    }
+
+    fn value(&self, operand: Operand<W>) -> Option<W> {
+        match operand {
+            Operand::Immediate(value) => Some(value),
+            _ => self.addr(operand).map(|addr| self.read(addr)),
+        }
+    }
+
+    /// The address an operand writes to. Immediate write parameters are
+    /// treated like position mode, as in [`Program::get_addr`].
+    fn addr(&self, operand: Operand<W>) -> Option<usize> {
+        match operand {
+            Operand::Immediate(addr) => addr.to_usize(),
+            Operand::Position(addr) => Some(addr),
+            Operand::Relative(offset) => self
+                .overflow
+                .add(offset, self.relative_base.clone())?
+                .to_usize(),
+        }
+    }
+
+    /// Execute one instruction through the cache, deferring to
+    /// [`Program::execute`] for anything that fails so errors are reported
+    /// the same way.
+    pub(crate) fn execute_cached(&mut self) -> Result<StepResult<W>, IntcodeError<W>> {
+        match self
+            .decode_cached()
+            .and_then(|decoded| self.run_decoded(decoded))
+        {
+            Some(result) => Ok(result),
+            None => self.execute(),
+        }
+    }
+
+    /// Run a decoded instruction, or return `None` without changing anything
+    /// if it fails.
+    fn run_decoded(&mut self, decoded: Decoded<W>) -> Option<StepResult<W>> {
+        let [a, b, c] = decoded.operands;
+        let overflow = self.overflow;
+        let binop = |program: &mut Program<W>, f: &dyn Fn(W, W) -> Option<W>| {
+            let value = f(program.value(a.clone())?, program.value(b.clone())?)?;
+            program.write(program.addr(c.clone())?, value);
+            Some(())
+        };
+        let mut result = StepResult::Continue;
+        match decoded.opcode {
+            Opcode::Add => binop(self, &|x, y| overflow.add(x, y))?,
+            Opcode::Multiply => binop(self, &|x, y| overflow.mul(x, y))?,
+            Opcode::LessThan => binop(self, &|x, y| Some(W::from_i64(i64::from(x < y))))?,
+            Opcode::Equals => binop(self, &|x, y| Some(W::from_i64(i64::from(x == y))))?,
+            Opcode::Input => {
+                let addr = self.addr(a)?;
+                let Some(value) = self.inputs.pop_front() else {
+                    return Some(StepResult::NeedsInput);
+                };
+                self.write(addr, value);
+            }
+            Opcode::Output => result = StepResult::Output(self.value(a)?),
+            Opcode::JumpIfTrue | Opcode::JumpIfFalse => {
+                let jump = self.value(a)?.is_zero() == (decoded.opcode == Opcode::JumpIfFalse);
+                if jump {
+                    self.ip = self.value(b)?.to_usize()?;
+                    return Some(result);
+                }
+            }
+            Opcode::AdjustRelativeBase => {
+                self.relative_base = overflow.add(self.relative_base.clone(), self.value(a)?)?;
+            }
+            Opcode::Halt => return Some(StepResult::Halted),
+        }
+        self.ip += usize::from(decoded.size);
+        Some(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Run `program` with and without the cache, comparing every step.
+    fn assert_same(memory: Vec<i64>, inputs: &[i64], max_steps: usize) {
+        let mut reference = Program::new(memory);
+        reference.inputs.extend(inputs);
+        let mut cached = reference.clone();
+        cached.enable_decode_cache();
+        for _ in 0..max_steps {
+            let expected = reference.step().map_err(|err| err.to_string());
+            let actual = cached.step().map_err(|err| err.to_string());
+            assert_eq!(actual, expected);
+            assert_eq!(cached.ip(), reference.ip());
+            assert_eq!(cached.relative_base(), reference.relative_base());
+            assert_eq!(cached.memory(), reference.memory());
+            if matches!(
+                expected,
+                Ok(StepResult::Halted | StepResult::NeedsInput) | Err(_)
+            ) {
+                return;
+            }
+        }
+    }
+
+    #[test]
+    fn examples() {
+        // quine
+        let quine = vec![
+            109, 1, 204, -1, 1001, 100, 1, 100, 1008, 100, 16, 101, 1006, 101, 0, 99,
+        ];
+        assert_same(quine, &[], 1000);
+        // compare the input to 8
+        let compare = vec![
+            3, 21, 1008, 21, 8, 20, 1005, 20, 22, 107, 8, 21, 20, 1006, 20, 31, 1106, 0, 36, 98, 0,
+            0, 1002, 21, 125, 20, 4, 20, 1105, 1, 46, 104, 999, 1105, 1, 46, 1101, 1000, 1, 20, 4,
+            20, 1105, 1, 46, 98, 99,
+        ];
+        for input in 6..10 {
+            assert_same(compare.clone(), &[input], 1000);
+        }
+    }
+
+    #[test]
+    fn self_modifying() {
+        // count to three by incrementing the output instruction's operand
+        let memory = vec![
+            104, 0, 1001, 1, 1, 1, 1007, 1, 3, 20, 1005, 20, 0, 99, 0, 0, 0, 0, 0, 0, 0,
+        ];
+        assert_same(memory.clone(), &[], 1000);
+
+        let mut program = Program::new(memory);
+        program.enable_decode_cache();
+        let outputs: Vec<_> = program.outputs([]).map(Result::unwrap).collect();
+        assert_eq!(outputs, [0, 1, 2]);
+    }
+
+    #[test]
+    fn errors() {
+        assert_same(vec![1, 0, 0, 0, 42], &[], 10);
+        assert_same(vec![4, -5, 99], &[], 10);
+        assert_same(vec![109, -10, 204, 0, 99], &[], 10);
+        assert_same(vec![1105, 1, -2, 99], &[], 10);
+        assert_same(vec![3, 0, 99], &[], 10);
+    }
+
+    #[test]
+    fn random_programs() {
+        // small words so that most are valid opcodes, modes and addresses,
+        // and no multiplication so that values can't overflow in 40 steps
+        let mut seed: u64 = 0x2545_f491_4f6c_dd1d;
+        let mut next = |n: u64| {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            seed % n
+        };
+        let words = [
+            1, 3, 4, 5, 6, 7, 8, 9, 99, 101, 1001, 1105, 1106, 203, 204, 209, 21101,
+        ];
+        for _ in 0..500 {
+            let memory: Vec<i64> = (0..40)
+                .map(|_| match next(3) {
+                    0 => words[next(words.len() as u64) as usize],
+                    _ => next(40) as i64 - 2,
+                })
+                .collect();
+            assert_same(memory, &[3, -1, 7, 0, 12], 40);
+        }
+    }
+}
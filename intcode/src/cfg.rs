@@ -0,0 +1,310 @@
+//! Control-flow graphs of Intcode programs.
+//!
+//! [`Cfg::build`] follows the code reachable from address 0, taking both
+//! sides of a conditional jump unless its condition is an immediate value.
+//! Only immediate-mode jump targets can be followed; jumps through memory,
+//! like subroutine returns, are recorded as unresolved.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Write,
+    ops::Range,
+};
+
+use crate::{disasm, Instruction, Opcode, ParamMode};
+
+/// A straight-line run of instructions only entered at its start.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BasicBlock {
+    pub start: usize,
+    /// Each instruction with its address.
+    pub instructions: Vec<(usize, Instruction)>,
+}
+
+impl BasicBlock {
+    /// The address after the last instruction.
+    #[must_use]
+    pub fn end(&self) -> usize {
+        self.instructions
+            .last()
+            .map_or(self.start, |&(addr, instruction)| addr + instruction.size())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EdgeKind {
+    /// A taken jump.
+    Jump,
+    /// Execution continuing to the next address.
+    FallThrough,
+}
+
+/// An edge between the blocks starting at `from` and `to`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Edge {
+    pub from: usize,
+    pub to: usize,
+    pub kind: EdgeKind,
+}
+
+/// The basic blocks of a program and the edges between them.
+#[derive(Debug, Clone)]
+pub struct Cfg {
+    blocks: BTreeMap<usize, BasicBlock>,
+    edges: Vec<Edge>,
+    unresolved: Vec<usize>,
+}
+
+fn word(memory: &[i64], addr: usize) -> i64 {
+    memory.get(addr).copied().unwrap_or(0)
+}
+
+/// Where control can go after the instruction at `addr`, and whether it
+/// has a jump target that can't be known statically.
+fn successors(
+    memory: &[i64],
+    addr: usize,
+    instruction: Instruction,
+) -> (Vec<(usize, EdgeKind)>, bool) {
+    let next = addr + instruction.size();
+    match instruction.opcode {
+        Opcode::Halt => (Vec::new(), false),
+        Opcode::JumpIfTrue | Opcode::JumpIfFalse => {
+            let condition = (instruction.modes[0] == ParamMode::Immediate).then(|| {
+                (word(memory, addr + 1) != 0) == (instruction.opcode == Opcode::JumpIfTrue)
+            });
+            let target = match instruction.modes[1] {
+                ParamMode::Immediate => usize::try_from(word(memory, addr + 2)).ok(),
+                _ => None,
+            };
+            let mut successors = Vec::new();
+            if condition != Some(true) {
+                successors.push((next, EdgeKind::FallThrough));
+            }
+            if condition != Some(false) {
+                successors.extend(target.map(|target| (target, EdgeKind::Jump)));
+            }
+            (successors, condition != Some(false) && target.is_none())
+        }
+        _ => (vec![(next, EdgeKind::FallThrough)], false),
+    }
+}
+
+impl Cfg {
+    /// Find the blocks reachable from address 0 of a memory image.
+    #[must_use]
+    pub fn build(memory: &[i64]) -> Cfg {
+        let mut instructions = BTreeMap::new();
+        let mut leaders = BTreeSet::from([0]);
+        let mut work = vec![0];
+        while let Some(addr) = work.pop() {
+            if instructions.contains_key(&addr) {
+                continue;
+            }
+            let Ok(instruction) = Instruction::decode(word(memory, addr)) else {
+                continue;
+            };
+            instructions.insert(addr, instruction);
+            let (successors, _) = successors(memory, addr, instruction);
+            let is_jump = matches!(instruction.opcode, Opcode::JumpIfTrue | Opcode::JumpIfFalse);
+            for (target, _) in successors {
+                if is_jump {
+                    leaders.insert(target);
+                }
+                work.push(target);
+            }
+        }
+
+        let mut cfg = Cfg {
+            blocks: BTreeMap::new(),
+            edges: Vec::new(),
+            unresolved: Vec::new(),
+        };
+        for &start in &leaders {
+            let mut block = BasicBlock {
+                start,
+                instructions: Vec::new(),
+            };
+            let mut addr = start;
+            while let Some(&instruction) = instructions.get(&addr) {
+                block.instructions.push((addr, instruction));
+                let (successors, unresolved) = successors(memory, addr, instruction);
+                if unresolved {
+                    cfg.unresolved.push(addr);
+                }
+                addr += instruction.size();
+                let falls_through = successors == [(addr, EdgeKind::FallThrough)];
+                if !falls_through || leaders.contains(&addr) {
+                    cfg.edges
+                        .extend(successors.into_iter().map(|(to, kind)| Edge {
+                            from: start,
+                            to,
+                            kind,
+                        }));
+                    break;
+                }
+            }
+            if !block.instructions.is_empty() {
+                cfg.blocks.insert(start, block);
+            }
+        }
+        // drop edges into addresses that don't decode
+        cfg.edges.retain(|edge| cfg.blocks.contains_key(&edge.to));
+        cfg
+    }
+
+    pub fn blocks(&self) -> impl Iterator<Item = &BasicBlock> {
+        self.blocks.values()
+    }
+
+    /// The block starting at `addr`.
+    #[must_use]
+    pub fn block(&self, addr: usize) -> Option<&BasicBlock> {
+        self.blocks.get(&addr)
+    }
+
+    #[must_use]
+    pub fn edges(&self) -> &[Edge] {
+        &self.edges
+    }
+
+    /// Addresses of jumps whose target is read from memory, so can't be
+    /// followed.
+    #[must_use]
+    pub fn unresolved_jumps(&self) -> &[usize] {
+        &self.unresolved
+    }
+
+    /// Address ranges below `len` not covered by any reachable instruction:
+    /// data, or code only reached through unresolved jumps.
+    #[must_use]
+    pub fn unreachable(&self, len: usize) -> Vec<Range<usize>> {
+        let mut ranges = Vec::new();
+        let mut addr = 0;
+        for block in self.blocks() {
+            if block.start > addr {
+                ranges.push(addr..block.start.min(len));
+            }
+            addr = addr.max(block.end());
+        }
+        if addr < len {
+            ranges.push(addr..len);
+        }
+        ranges.retain(|range| !range.is_empty());
+        ranges
+    }
+
+    /// Export the graph in Graphviz DOT format, with each block labelled by
+    /// its disassembly.
+    #[must_use]
+    pub fn to_dot(&self, memory: &[i64]) -> String {
+        let mut dot = String::from("digraph intcode {\n");
+        dot.push_str("    node [shape=box, fontname=\"monospace\"];\n");
+        for block in self.blocks() {
+            let mut label = String::new();
+            for &(addr, _) in &block.instructions {
+                let line = disasm::disassemble_at(memory, addr);
+                write!(label, "{addr}: {}\\l", line.text()).unwrap();
+            }
+            writeln!(dot, "    b{} [label=\"{label}\"];", block.start).unwrap();
+        }
+        for edge in &self.edges {
+            let style = match edge.kind {
+                EdgeKind::Jump => " [color=blue]",
+                EdgeKind::FallThrough => "",
+            };
+            writeln!(dot, "    b{} -> b{}{style};", edge.from, edge.to).unwrap();
+        }
+        if !self.unresolved.is_empty() {
+            dot.push_str("    unresolved [shape=ellipse, label=\"?\"];\n");
+            for &addr in &self.unresolved {
+                let block = self
+                    .blocks()
+                    .find(|block| (block.start..block.end()).contains(&addr));
+                if let Some(block) = block {
+                    writeln!(dot, "    b{} -> unresolved [style=dashed];", block.start).unwrap();
+                }
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm;
+
+    #[test]
+    fn blocks_and_edges() {
+        let memory = asm::assemble(
+            "
+            start:  IN [x]
+                    JF [x], #end
+                    ADD [x], #1, [x]
+                    OUT [x]
+                    JT #1, #start
+            end:    HALT
+            x:      .data 0
+            ",
+        )
+        .unwrap();
+        let cfg = Cfg::build(&memory);
+        let starts: Vec<_> = cfg.blocks().map(|block| block.start).collect();
+        assert_eq!(starts, [0, 5, 14]);
+        assert_eq!(cfg.block(5).unwrap().end(), 14);
+        let edges: Vec<_> = cfg
+            .edges()
+            .iter()
+            .map(|edge| (edge.from, edge.to, edge.kind))
+            .collect();
+        assert_eq!(
+            edges,
+            [
+                (0, 5, EdgeKind::FallThrough),
+                (0, 14, EdgeKind::Jump),
+                (5, 0, EdgeKind::Jump),
+            ]
+        );
+        assert!(cfg.unresolved_jumps().is_empty());
+        assert_eq!(
+            cfg.unreachable(memory.len()),
+            [Range { start: 15, end: 16 }]
+        );
+    }
+
+    #[test]
+    fn subroutines() {
+        // call a subroutine that returns through an address on the stack
+        let memory = asm::assemble(
+            "
+                    ARB #stack
+                    ADD #back, #0, [rb]
+                    JT #1, #double
+            back:   OUT [x]
+                    HALT
+            double: MUL [x], #2, [x]
+                    JF #0, [rb]
+            x:      .data 21
+            unused: .data 99
+            stack:  .data 0
+            ",
+        )
+        .unwrap();
+        let cfg = Cfg::build(&memory);
+        let starts: Vec<_> = cfg.blocks().map(|block| block.start).collect();
+        assert_eq!(starts, [0, 12]);
+        assert_eq!(cfg.unresolved_jumps(), [16]);
+        // the return address is only reached through the unresolved jump
+        assert_eq!(cfg.unreachable(memory.len()), [9..12, 19..22]);
+
+        let dot = cfg.to_dot(&memory);
+        assert!(dot.starts_with("digraph intcode {\n"));
+        assert!(dot.contains(
+            "    b0 [label=\"0: ARB #21\\l2: ADD #9, #0, [rb+0]\\l6: JT #1, #12\\l\"];\n"
+        ));
+        assert!(dot.contains("    b0 -> b12 [color=blue];\n"));
+        assert!(dot.contains("    b12 -> unresolved [style=dashed];\n"));
+    }
+}
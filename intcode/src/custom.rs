@@ -0,0 +1,394 @@
+//! Host-defined opcodes.
+//!
+//! [`Program::register_opcode`] adds an opcode that the built-in instruction
+//! set doesn't use, with a parameter count and a handler. When the program
+//! reaches it, the parameters are resolved according to their modes and the
+//! handler is called with a [`Context`] to read and change the machine.
+//!
+//! Custom opcodes only run in the interpreter: the disassembler shows them as
+//! data and the transpiler treats them as errors. Writes made by a handler are
+//! recorded in traces and history like those of built-in instructions, and
+//! undone if the handler fails.
+
+use std::{collections::HashMap, sync::Arc};
+
+use crate::{
+    trace::MemoryWrite, IntcodeError, Memory, Opcode, ParamMode, Program, StepResult, Word,
+};
+
+/// A resolved parameter of a custom instruction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Operand<W = i64> {
+    pub mode: ParamMode,
+    /// The immediate value, or the value at the parameter's address.
+    pub value: W,
+    /// The address a position or relative parameter refers to.
+    pub addr: Option<usize>,
+}
+
+/// What the machine does after a custom instruction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Effect<W = i64> {
+    /// Continue with the next instruction.
+    Continue,
+    /// Output a value and continue with the next instruction.
+    Output(W),
+    /// Continue at an address.
+    Jump(usize),
+    /// Stop without moving the instruction pointer, like a halt instruction.
+    Halt,
+}
+
+type Handler<W> = dyn Fn(&mut Context<'_, W>) -> Result<Effect<W>, String> + Send + Sync;
+
+#[derive(Clone)]
+pub(crate) struct CustomOpcode<W> {
+    params: usize,
+    handler: Arc<Handler<W>>,
+}
+
+/// Custom opcodes by code, shared between clones of a program.
+pub(crate) type CustomOpcodes<W> = Arc<HashMap<i64, CustomOpcode<W>>>;
+
+/// The machine as seen by a custom opcode handler.
+pub struct Context<'a, W = i64> {
+    program: &'a mut Program<W>,
+    operands: Vec<Operand<W>>,
+    writes: Vec<MemoryWrite<W>>,
+}
+
+impl<W: Word> Context<'_, W> {
+    /// The address of the instruction being executed.
+    #[must_use]
+    pub fn ip(&self) -> usize {
+        self.program.ip
+    }
+
+    #[must_use]
+    pub fn relative_base(&self) -> W {
+        self.program.relative_base.clone()
+    }
+
+    pub fn set_relative_base(&mut self, relative_base: W) {
+        self.program.relative_base = relative_base;
+    }
+
+    #[must_use]
+    pub fn memory(&self) -> &Memory<W> {
+        &self.program.memory
+    }
+
+    #[must_use]
+    pub fn operands(&self) -> &[Operand<W>] {
+        &self.operands
+    }
+
+    /// The value of parameter `index`.
+    #[must_use]
+    pub fn value(&self, index: usize) -> W {
+        self.operands[index].value.clone()
+    }
+
+    /// Write to the address parameter `index` refers to. Fails for
+    /// immediate parameters.
+    pub fn write(&mut self, index: usize, value: W) -> Result<(), String> {
+        let addr = self.operands[index]
+            .addr
+            .ok_or_else(|| format!("parameter {index} is immediate"))?;
+        self.poke(addr, value);
+        Ok(())
+    }
+
+    /// Write to any address.
+    pub fn poke(&mut self, addr: usize, value: W) {
+        let old = self.program.read(addr);
+        self.program.write(addr, value.clone());
+        self.writes.push(MemoryWrite {
+            addr,
+            old,
+            new: value,
+        });
+    }
+}
+
+impl<W: Word> Program<W> {
+    /// Handle `code` with `handler`, which gets `params` parameters resolved
+    /// using the parameter modes of the instruction word. Registering a code
+    /// again replaces its handler.
+    ///
+    /// # Panics
+    ///
+    /// If `code` is a built-in opcode or isn't between 0 and 99, since only
+    /// the last two digits of an instruction are its opcode.
+    pub fn register_opcode<F>(&mut self, code: i64, params: usize, handler: F)
+    where
+        F: Fn(&mut Context<'_, W>) -> Result<Effect<W>, String> + Send + Sync + 'static,
+    {
+        assert!(
+            (0..100).contains(&code) && Opcode::from_code(code).is_none(),
+            "opcode {code} can't be registered"
+        );
+        let custom = CustomOpcode {
+            params,
+            handler: Arc::new(handler),
+        };
+        Arc::make_mut(self.custom.get_or_insert_with(Arc::default)).insert(code, custom);
+    }
+
+    /// Run the custom opcode that `err` says is unknown, or return `err` if
+    /// there is none.
+    pub(crate) fn execute_custom(
+        &mut self,
+        err: IntcodeError<W>,
+    ) -> Result<StepResult<W>, IntcodeError<W>> {
+        let IntcodeError::UnknownOpcode { opcode, .. } = err else {
+            return Err(err);
+        };
+        let Some(custom) = self.custom.as_ref().and_then(|custom| custom.get(&opcode)) else {
+            return Err(err);
+        };
+        let custom = custom.clone();
+
+        let ip = self.ip;
+        let instruction = self.read(ip);
+        // only the opcode digits are known to fit in an i64 so far
+        let mut digits = instruction.to_i64().unwrap_or_default() / 100;
+        let mut operands = Vec::with_capacity(custom.params);
+        for offset in 1..=custom.params {
+            let mode = match digits % 10 {
+                0 => ParamMode::Position,
+                1 => ParamMode::Immediate,
+                2 => ParamMode::Relative,
+                mode => {
+                    return Err(IntcodeError::UnknownParamMode {
+                        ip,
+                        instruction,
+                        mode,
+                    })
+                }
+            };
+            digits /= 10;
+            let addr = match mode {
+                ParamMode::Immediate => None,
+                _ => Some(self.get_addr(offset, mode)?),
+            };
+            let value = match addr {
+                Some(addr) => self.read(addr),
+                None => self.read(ip + offset),
+            };
+            operands.push(Operand { mode, value, addr });
+        }
+
+        let relative_base = self.relative_base.clone();
+        let memory_len = self.memory.len();
+        let mut context = Context {
+            program: self,
+            operands,
+            writes: Vec::new(),
+        };
+        let effect = (custom.handler)(&mut context);
+        let writes = context.writes;
+        let effect = match effect {
+            Ok(effect) => effect,
+            Err(message) => {
+                for write in writes.into_iter().rev() {
+                    self.write(write.addr, write.old);
+                }
+                self.memory.truncate(memory_len);
+                self.relative_base = relative_base;
+                return Err(IntcodeError::Custom {
+                    ip,
+                    instruction,
+                    message,
+                });
+            }
+        };
+        if self.trace.is_some() || self.history.is_some() {
+            self.custom_writes = writes;
+        }
+        let next = ip + 1 + custom.params;
+        Ok(match effect {
+            Effect::Continue => {
+                self.ip = next;
+                StepResult::Continue
+            }
+            Effect::Output(value) => {
+                self.ip = next;
+                StepResult::Output(value)
+            }
+            Effect::Jump(target) => {
+                self.ip = target;
+                StepResult::Continue
+            }
+            Effect::Halt => StepResult::Halted,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+
+    /// Opcode 10: `MOD a, b, c` stores `a % b` in `c`.
+    fn register_mod(program: &mut Program) {
+        program.register_opcode(10, 3, |context| {
+            let (a, b) = (context.value(0), context.value(1));
+            if b == 0 {
+                return Err("division by zero".to_string());
+            }
+            context.write(2, a % b)?;
+            Ok(Effect::Continue)
+        });
+    }
+
+    #[test]
+    fn dialect() {
+        // output 17 % 5, then 100 % [x], then jump over the halt with opcode
+        // 11 and halt with opcode 12
+        let memory = vec![
+            1110, 17, 5, 20, 4, 20, 110, 100, 21, 22, 4, 22, 111, 16, 99, 99, 12, 99, 0, 0, 0, 7, 0,
+        ];
+        let mut program = Program::new(memory);
+        register_mod(&mut program);
+        program.register_opcode(11, 1, |context| {
+            Ok(Effect::Jump(context.value(0).try_into().unwrap()))
+        });
+        program.register_opcode(12, 0, |_| Ok(Effect::Halt));
+        for cached in [false, true] {
+            let mut program = program.clone();
+            if cached {
+                program.enable_decode_cache();
+            }
+            let mut output = Vec::new();
+            program.run(None, &mut output).unwrap();
+            assert_eq!(output, [2, 2]);
+            assert_eq!(program.ip(), 16);
+        }
+
+        // unregistered programs still reject the opcodes
+        let mut plain = Program::new(vec![12, 99]);
+        assert!(matches!(
+            plain.step(),
+            Err(IntcodeError::UnknownOpcode { opcode: 12, .. })
+        ));
+    }
+
+    #[test]
+    fn intrinsics() {
+        // opcode 50 dumps the registers and a memory word, opcode 51 outputs
+        // the relative base
+        let dumps = Arc::new(Mutex::new(Vec::new()));
+        let mut program = Program::new(vec![109, 7, 250, 0, 51, 99, 99, 42]);
+        let log = Arc::clone(&dumps);
+        program.register_opcode(50, 1, move |context| {
+            let operand = &context.operands()[0];
+            log.lock().unwrap().push(format!(
+                "ip={} rb={} [{:?}]={}",
+                context.ip(),
+                context.relative_base(),
+                operand.addr,
+                operand.value
+            ));
+            Ok(Effect::Continue)
+        });
+        program.register_opcode(51, 0, |context| Ok(Effect::Output(context.relative_base())));
+        let mut output = Vec::new();
+        program.run(None, &mut output).unwrap();
+        assert_eq!(output, [7]);
+        assert_eq!(*dumps.lock().unwrap(), ["ip=2 rb=7 [Some(7)]=42"]);
+    }
+
+    #[test]
+    fn failing_output() {
+        // custom outputs of different sizes report their own address when
+        // the output can't be written
+        let mut program = Program::new(vec![13, 99]);
+        program.register_opcode(13, 0, |_| Ok(Effect::Output(7)));
+        let mut sum = Program::new(vec![1101, 1, 1, 8, 1114, 3, 4, 99, 0]);
+        sum.register_opcode(14, 2, |context| {
+            Ok(Effect::Output(context.value(0) + context.value(1)))
+        });
+        for (program, ip) in [(program, 0), (sum, 4)] {
+            for run in 0..3 {
+                let mut program = program.clone();
+                let (sender, receiver) = std::sync::mpsc::channel();
+                drop(receiver);
+                let err = match run {
+                    0 => program.run(None, sender).unwrap_err(),
+                    1 => program.run_with_input(0, sender).unwrap_err(),
+                    _ => program.run_with_fuel(None, sender, 10).unwrap_err(),
+                };
+                assert!(
+                    matches!(err, IntcodeError::Output { ip: at, .. } if at == ip),
+                    "{err}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn errors() {
+        let mut program = Program::new(vec![11110, 1, 0, 0, 99]);
+        register_mod(&mut program);
+        let err = program.run(None, Vec::new()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "instruction 11110 at address 0 failed: division by zero"
+        );
+
+        let mut program = Program::new(vec![11110, 1, 2, 0, 99]);
+        register_mod(&mut program);
+        let err = program.run(None, Vec::new()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "instruction 11110 at address 0 failed: parameter 2 is immediate"
+        );
+
+        let mut program = Program::new(vec![310, 1, 2, 0, 99]);
+        register_mod(&mut program);
+        assert!(matches!(
+            program.step(),
+            Err(IntcodeError::UnknownParamMode { mode: 3, .. })
+        ));
+    }
+
+    #[test]
+    fn recorded() {
+        // opcode 20 swaps two words, then the program outputs both
+        let memory = vec![20, 8, 9, 4, 8, 4, 9, 99, 1, 2];
+        let mut program = Program::new(memory.clone());
+        program.register_opcode(20, 2, |context| {
+            let (a, b) = (context.value(0), context.value(1));
+            context.write(0, b)?;
+            context.write(1, a)?;
+            Ok(Effect::Continue)
+        });
+        program.enable_history();
+        program.start_trace();
+        let mut output = Vec::new();
+        program.run(None, &mut output).unwrap();
+        assert_eq!(output, [2, 1]);
+
+        let trace = program.take_trace().unwrap();
+        assert_eq!(trace.entries()[0].writes.len(), 2);
+        assert_eq!(trace.state_at(1).unwrap().memory(), program.memory());
+
+        assert!(program.run_back_to(0));
+        assert_eq!(program.memory().to_vec(), memory);
+        assert!(!program.step_back());
+
+        // a failing handler leaves memory as it was
+        let mut program = Program::new(vec![21, 0]);
+        program.register_opcode(21, 0, |context| {
+            context.poke(0, 7);
+            context.poke(100, 7);
+            Err("failed".to_string())
+        });
+        program.enable_history();
+        assert!(program.step().is_err());
+        assert_eq!(program.memory().to_vec(), [21, 0]);
+        assert_eq!(program.history_len(), 0);
+    }
+}
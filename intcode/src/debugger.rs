@@ -0,0 +1,428 @@
+use std::{
+    collections::{BTreeSet, HashSet},
+    fmt::Write,
+};
+
+use crate::{disasm, snapshot::Snapshot, Instruction, Opcode, Program, StepResult};
+
+const HELP: &str = "\
+commands:
+  s, step [n]            execute n instructions (default 1, at most 1000000)
+  c, continue [n]        run until a breakpoint, input is needed or the program halts,
+                         pausing after n instructions (default 1000000)
+  b, break [addr]        set a breakpoint at addr, or list breakpoints
+  b op <mnemonic>        break before every instruction with this opcode
+  d, delete <addr>       delete the breakpoint at addr
+  d op <mnemonic>        delete an opcode breakpoint
+  r, regs                show ip, relative base and the next instruction
+  x <addr> [len]         show len words of memory (default 8, at most 4096)
+  poke <addr> <value>    write value to memory at addr
+  dis [addr] [n]         disassemble n instructions from addr (default ip 10, at most 4096)
+  in [values...]         queue input values, or show the input queue
+  in clear               clear the input queue
+  out                    show collected outputs
+  out clear              clear collected outputs
+  save <path>            save the program state and outputs to a snapshot file
+  load <path>            restore the program state and outputs from a snapshot file
+  q, quit                exit";
+
+/// How many instructions `continue` or `step` runs before pausing, so a
+/// program stuck in a loop hands control back.
+const CONTINUE_STEPS: usize = 1_000_000;
+
+/// The most words `x` or instructions `dis` will list at once.
+const MAX_LISTING: usize = 4096;
+
+/// An interactive debugger around a [`Program`].
+///
+/// Commands are passed as text lines to [`Debugger::execute`], which returns
+/// the text to show the user.
+pub struct Debugger {
+    program: Program,
+    breakpoints: BTreeSet<usize>,
+    opcode_breakpoints: HashSet<Opcode>,
+    outputs: Vec<i64>,
+}
+
+fn parse<T: std::str::FromStr>(arg: Option<&str>, name: &str) -> Result<T, String> {
+    let arg = arg.ok_or_else(|| format!("missing {name}"))?;
+    arg.parse().map_err(|_| format!("invalid {name} '{arg}'"))
+}
+
+fn parse_opcode(arg: Option<&str>) -> Result<Opcode, String> {
+    let arg = arg.ok_or("missing mnemonic")?;
+    Opcode::from_mnemonic(arg).ok_or_else(|| format!("unknown mnemonic '{arg}'"))
+}
+
+impl Debugger {
+    #[must_use]
+    pub fn new(program: Program) -> Debugger {
+        Debugger {
+            program,
+            breakpoints: BTreeSet::new(),
+            opcode_breakpoints: HashSet::new(),
+            outputs: Vec::new(),
+        }
+    }
+
+    #[must_use]
+    pub fn program(&self) -> &Program {
+        &self.program
+    }
+
+    #[must_use]
+    pub fn outputs(&self) -> &[i64] {
+        &self.outputs
+    }
+
+    /// Run one command line. Returns `None` when the user asks to quit.
+    pub fn execute(&mut self, line: &str) -> Option<String> {
+        let mut args = line.split_whitespace();
+        let Some(command) = args.next() else {
+            return Some(String::new());
+        };
+        let args: Vec<_> = args.collect();
+        let result = match command {
+            "q" | "quit" => return None,
+            "h" | "help" => Ok(HELP.to_string()),
+            "s" | "step" => self.step(&args),
+            "c" | "continue" => self.continue_(&args),
+            "b" | "break" => self.add_breakpoint(&args),
+            "d" | "delete" => self.delete_breakpoint(&args),
+            "r" | "regs" => Ok(self.registers()),
+            "x" => self.examine(&args),
+            "poke" => self.poke(&args),
+            "dis" => self.disassemble(&args),
+            "in" => self.input(&args),
+            "out" => Ok(self.output(&args)),
+            "save" => self.save(&args),
+            "load" => self.load(&args),
+            _ => Err(format!("unknown command '{command}', try 'help'")),
+        };
+        Some(result.unwrap_or_else(|err| format!("error: {err}")))
+    }
+
+    /// Execute one instruction, appending any message to `text`. Returns
+    /// true if execution can't continue.
+    fn step_once(&mut self, text: &mut String) -> bool {
+        match self.program.step() {
+            Ok(StepResult::Continue) => false,
+            Ok(StepResult::Output(value)) => {
+                self.outputs.push(value);
+                writeln!(text, "output: {value}").unwrap();
+                false
+            }
+            Ok(StepResult::NeedsInput) => {
+                writeln!(
+                    text,
+                    "waiting for input at {}, queue values with 'in'",
+                    self.program.ip()
+                )
+                .unwrap();
+                true
+            }
+            Ok(StepResult::Halted) => {
+                writeln!(text, "halted").unwrap();
+                true
+            }
+            Err(err) => {
+                writeln!(text, "error: {err}").unwrap();
+                true
+            }
+        }
+    }
+
+    fn at_breakpoint(&self) -> bool {
+        let ip = self.program.ip();
+        if self.breakpoints.contains(&ip) {
+            return true;
+        }
+        Instruction::decode(self.program.memory().get(ip))
+            .is_ok_and(|instruction| self.opcode_breakpoints.contains(&instruction.opcode))
+    }
+
+    fn step(&mut self, args: &[&str]) -> Result<String, String> {
+        let count: usize = match args.first() {
+            Some(_) => parse(args.first().copied(), "count")?,
+            None => 1,
+        };
+        let mut text = String::new();
+        let stopped = (0..count.min(CONTINUE_STEPS)).any(|_| self.step_once(&mut text));
+        if !stopped && count > CONTINUE_STEPS {
+            writeln!(text, "paused after {CONTINUE_STEPS} instructions").unwrap();
+        }
+        text.push_str(&self.registers());
+        Ok(text)
+    }
+
+    fn continue_(&mut self, args: &[&str]) -> Result<String, String> {
+        let limit: usize = match args.first() {
+            Some(_) => parse(args.first().copied(), "count")?,
+            None => CONTINUE_STEPS,
+        };
+        let mut text = String::new();
+        let mut steps = 0;
+        loop {
+            if steps == limit {
+                writeln!(text, "paused after {limit} instructions").unwrap();
+                break;
+            }
+            steps += 1;
+            if self.step_once(&mut text) {
+                break;
+            }
+            if self.at_breakpoint() {
+                writeln!(text, "breakpoint at {}", self.program.ip()).unwrap();
+                break;
+            }
+        }
+        text.push_str(&self.registers());
+        Ok(text)
+    }
+
+    fn add_breakpoint(&mut self, args: &[&str]) -> Result<String, String> {
+        match args {
+            [] => {
+                let mut lines: Vec<_> = self.breakpoints.iter().map(usize::to_string).collect();
+                let mut opcodes: Vec<_> = self
+                    .opcode_breakpoints
+                    .iter()
+                    .map(|opcode| format!("op {}", opcode.mnemonic()))
+                    .collect();
+                opcodes.sort();
+                lines.extend(opcodes);
+                if lines.is_empty() {
+                    Ok("no breakpoints".to_string())
+                } else {
+                    Ok(lines.join("\n"))
+                }
+            }
+            ["op", rest @ ..] => {
+                let opcode = parse_opcode(rest.first().copied())?;
+                self.opcode_breakpoints.insert(opcode);
+                Ok(format!("breakpoint on {}", opcode.mnemonic()))
+            }
+            [addr, ..] => {
+                let addr = parse(Some(addr), "address")?;
+                self.breakpoints.insert(addr);
+                Ok(format!("breakpoint at {addr}"))
+            }
+        }
+    }
+
+    fn delete_breakpoint(&mut self, args: &[&str]) -> Result<String, String> {
+        let removed = match args {
+            ["op", rest @ ..] => self
+                .opcode_breakpoints
+                .remove(&parse_opcode(rest.first().copied())?),
+            _ => self
+                .breakpoints
+                .remove(&parse(args.first().copied(), "address")?),
+        };
+        if removed {
+            Ok("deleted".to_string())
+        } else {
+            Err("no such breakpoint".to_string())
+        }
+    }
+
+    fn registers(&self) -> String {
+        let line = disasm::disassemble_at(self.program.memory(), self.program.ip());
+        format!(
+            "ip={} rb={}\n{line}",
+            self.program.ip(),
+            self.program.relative_base()
+        )
+    }
+
+    fn examine(&self, args: &[&str]) -> Result<String, String> {
+        let addr: usize = parse(args.first().copied(), "address")?;
+        let len: usize = match args.get(1) {
+            Some(_) => parse(args.get(1).copied(), "length")?,
+            None => 8,
+        };
+        if len > MAX_LISTING {
+            return Err(format!("length must be at most {MAX_LISTING}"));
+        }
+        let end = addr
+            .checked_add(len)
+            .ok_or_else(|| format!("address {addr} + {len} is out of range"))?;
+        let memory = self.program.memory();
+        let mut text = String::new();
+        for start in (addr..end).step_by(8) {
+            let words: Vec<_> = (start..start.saturating_add(8).min(end))
+                .map(|addr| memory.get(addr).to_string())
+                .collect();
+            writeln!(text, "{start:>5}: {}", words.join(" ")).unwrap();
+        }
+        text.pop();
+        Ok(text)
+    }
+
+    fn poke(&mut self, args: &[&str]) -> Result<String, String> {
+        let addr = parse(args.first().copied(), "address")?;
+        let value = parse(args.get(1).copied(), "value")?;
+        self.program.poke(addr, value);
+        Ok(format!("[{addr}] = {value}"))
+    }
+
+    fn disassemble(&self, args: &[&str]) -> Result<String, String> {
+        let mut addr = match args.first() {
+            Some(_) => parse(args.first().copied(), "address")?,
+            None => self.program.ip(),
+        };
+        let count: usize = match args.get(1) {
+            Some(_) => parse(args.get(1).copied(), "count")?,
+            None => 10,
+        };
+        if count > MAX_LISTING {
+            return Err(format!("count must be at most {MAX_LISTING}"));
+        }
+        let mut lines = Vec::new();
+        for _ in 0..count {
+            let line = disasm::disassemble_at(self.program.memory(), addr);
+            lines.push(line.to_string());
+            // stop at the end of the address space
+            match addr.checked_add(line.words.len()) {
+                Some(next) => addr = next,
+                None => break,
+            }
+        }
+        Ok(lines.join("\n"))
+    }
+
+    fn input(&mut self, args: &[&str]) -> Result<String, String> {
+        let inputs = self.program.inputs_mut();
+        match args {
+            [] => {}
+            ["clear"] => inputs.clear(),
+            values => {
+                let values = values
+                    .iter()
+                    .map(|value| parse(Some(value), "input"))
+                    .collect::<Result<Vec<i64>, _>>()?;
+                inputs.extend(values);
+            }
+        }
+        let values: Vec<_> = inputs.iter().map(i64::to_string).collect();
+        Ok(format!("input queue: [{}]", values.join(", ")))
+    }
+
+    fn output(&mut self, args: &[&str]) -> String {
+        if args == ["clear"] {
+            self.outputs.clear();
+        }
+        let values: Vec<_> = self.outputs.iter().map(i64::to_string).collect();
+        format!("outputs: [{}]", values.join(", "))
+    }
+
+    fn save(&self, args: &[&str]) -> Result<String, String> {
+        let path = args.first().ok_or("missing path")?;
+        let snapshot = Snapshot {
+            program: self.program.clone(),
+            outputs: self.outputs.clone(),
+        };
+        snapshot.save(path).map_err(|err| err.to_string())?;
+        Ok(format!("saved to {path}"))
+    }
+
+    fn load(&mut self, args: &[&str]) -> Result<String, String> {
+        let path = args.first().ok_or("missing path")?;
+        let snapshot = Snapshot::load(path).map_err(|err| err.to_string())?;
+        self.program = snapshot.program;
+        self.outputs = snapshot.outputs;
+        Ok(format!("loaded from {path}\n{}", self.registers()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm;
+
+    #[test]
+    fn session() {
+        let memory = asm::assemble(
+            "
+            start:  IN [x]
+                    JF [x], #end
+                    ADD [x], #1, [x]
+                    OUT [x]
+                    JT #1, #start
+            end:    HALT
+            x:      .data 0
+            ",
+        )
+        .unwrap();
+        let mut debugger = Debugger::new(Program::new(memory));
+
+        let text = debugger.execute("c").unwrap();
+        assert!(text.starts_with("waiting for input at 0"));
+        assert_eq!(debugger.execute("in 4 0").unwrap(), "input queue: [4, 0]");
+        assert_eq!(debugger.execute("b op out").unwrap(), "breakpoint on OUT");
+        let text = debugger.execute("c").unwrap();
+        assert!(text.contains("breakpoint at 9"));
+        assert_eq!(debugger.execute("x 15 1").unwrap(), "   15: 5");
+        assert_eq!(debugger.execute("poke 15 41").unwrap(), "[15] = 41");
+        assert!(debugger.execute("s").unwrap().starts_with("output: 41"));
+        assert_eq!(debugger.execute("b 14").unwrap(), "breakpoint at 14");
+        assert_eq!(debugger.execute("d op OUT").unwrap(), "deleted");
+        let text = debugger.execute("c").unwrap();
+        assert!(text.contains("breakpoint at 14"));
+        assert!(text.ends_with("HALT"));
+        assert_eq!(debugger.execute("out").unwrap(), "outputs: [41]");
+        assert_eq!(debugger.execute("in").unwrap(), "input queue: []");
+        assert!(debugger.execute("c").unwrap().starts_with("halted"));
+        assert!(debugger.execute("q").is_none());
+    }
+
+    #[test]
+    fn bad_commands() {
+        let mut debugger = Debugger::new(Program::new(vec![99]));
+        assert_eq!(
+            debugger.execute("frobnicate").unwrap(),
+            "error: unknown command 'frobnicate', try 'help'"
+        );
+        assert_eq!(
+            debugger.execute("poke x 1").unwrap(),
+            "error: invalid address 'x'"
+        );
+        assert_eq!(
+            debugger.execute("b op NOP").unwrap(),
+            "error: unknown mnemonic 'NOP'"
+        );
+        assert_eq!(
+            debugger.execute("d 3").unwrap(),
+            "error: no such breakpoint"
+        );
+        assert_eq!(
+            debugger.execute("x 18446744073709551615 8").unwrap(),
+            "error: address 18446744073709551615 + 8 is out of range"
+        );
+        assert_eq!(
+            debugger.execute("x 0 1000000000000").unwrap(),
+            "error: length must be at most 4096"
+        );
+        assert_eq!(
+            debugger.execute("dis 0 5000").unwrap(),
+            "error: count must be at most 4096"
+        );
+    }
+
+    #[test]
+    fn limits() {
+        let mut debugger = Debugger::new(Program::new(vec![1105, 1, 0]));
+        let text = debugger.execute("c 100").unwrap();
+        assert!(text.starts_with("paused after 100 instructions\nip=0"));
+        let text = debugger.execute("s 999999999999").unwrap();
+        assert!(text.starts_with("paused after 1000000 instructions\nip=0"));
+        assert_eq!(
+            debugger.execute("x 18446744073709551613 2").unwrap(),
+            "18446744073709551613: 0 0"
+        );
+        debugger.program.poke(usize::MAX - 1, 1);
+        let text = debugger.execute("dis 18446744073709551614 3").unwrap();
+        assert_eq!(text.lines().count(), 2);
+        assert!(text.lines().next().unwrap().ends_with(".data 1"));
+    }
+}
@@ -0,0 +1,190 @@
+use std::fmt;
+
+use crate::{Instruction, Memory, ParamMode, Word};
+
+/// Format a parameter in assembly syntax: `#5`, `[12]` or `[rb+3]`.
+#[must_use]
+pub fn format_operand(mode: ParamMode, value: i64) -> String {
+    match mode {
+        ParamMode::Immediate => format!("#{value}"),
+        ParamMode::Position => format!("[{value}]"),
+        ParamMode::Relative if value < 0 => format!("[rb-{}]", value.unsigned_abs()),
+        ParamMode::Relative => format!("[rb+{value}]"),
+    }
+}
+
+/// What a line of a listing decodes to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Decoded {
+    Instruction(Instruction),
+    Data,
+}
+
+/// One line of a disassembly listing.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Line {
+    pub addr: usize,
+    pub words: Vec<i64>,
+    pub decoded: Decoded,
+}
+
+impl Line {
+    /// The assembly text of the line, without address or raw words.
+    #[must_use]
+    pub fn text(&self) -> String {
+        match &self.decoded {
+            Decoded::Instruction(instruction) => {
+                let operands: Vec<_> = instruction
+                    .param_modes()
+                    .iter()
+                    .zip(&self.words[1..])
+                    .map(|(&mode, &value)| format_operand(mode, value))
+                    .collect();
+                let mnemonic = instruction.opcode.mnemonic();
+                if operands.is_empty() {
+                    mnemonic.to_string()
+                } else {
+                    format!("{mnemonic} {}", operands.join(", "))
+                }
+            }
+            Decoded::Data => format!(".data {}", self.words[0]),
+        }
+    }
+}
+
+impl fmt::Display for Line {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let words: Vec<_> = self.words.iter().map(i64::to_string).collect();
+        write!(
+            f,
+            "{:>5}: {:<24} {}",
+            self.addr,
+            words.join(" "),
+            self.text()
+        )
+    }
+}
+
+/// Something that can be disassembled: a memory image or a program's
+/// [`Memory`].
+pub trait Words {
+    /// The word at `addr`, or `None` past the end of memory.
+    fn word(&self, addr: usize) -> Option<i64>;
+}
+
+impl Words for [i64] {
+    fn word(&self, addr: usize) -> Option<i64> {
+        self.get(addr).copied()
+    }
+}
+
+impl<const N: usize> Words for [i64; N] {
+    fn word(&self, addr: usize) -> Option<i64> {
+        self.get(addr).copied()
+    }
+}
+
+/// Words too large for an `i64` read as missing.
+impl<W: Word> Words for Memory<W> {
+    fn word(&self, addr: usize) -> Option<i64> {
+        (addr < self.len()).then(|| self.get(addr).to_i64())?
+    }
+}
+
+/// Decode the line starting at `addr`.
+///
+/// A word that is not a valid instruction, or whose parameters run past the
+/// end of memory or of the address space, is decoded as a single word of
+/// data. So is a word the assembler would not give back from the
+/// instruction's text: one with mode digits the opcode ignores, or an
+/// immediate mode for a parameter it writes.
+#[must_use]
+pub fn disassemble_at<M: Words + ?Sized>(memory: &M, addr: usize) -> Line {
+    let word = memory.word(addr).unwrap_or(0);
+    if let Some(instruction) = Instruction::decode(word)
+        .ok()
+        .filter(|instruction| reassembles(instruction, word))
+    {
+        let words: Option<Vec<_>> = addr
+            .checked_add(instruction.size() - 1)
+            .and_then(|last| (addr..=last).map(|addr| memory.word(addr)).collect());
+        if let Some(words) = words {
+            return Line {
+                addr,
+                words,
+                decoded: Decoded::Instruction(instruction),
+            };
+        }
+    }
+    Line {
+        addr,
+        words: vec![word],
+        decoded: Decoded::Data,
+    }
+}
+
+fn reassembles(instruction: &Instruction, word: i64) -> bool {
+    let writes_immediate = instruction
+        .opcode
+        .write_param()
+        .is_some_and(|index| instruction.modes[index] == ParamMode::Immediate);
+    instruction.encode() == word && !writes_immediate
+}
+
+/// Disassemble a whole memory image by a linear sweep from address 0.
+#[must_use]
+pub fn disassemble(memory: &[i64]) -> Vec<Line> {
+    let mut lines = Vec::new();
+    let mut addr = 0;
+    while addr < memory.len() {
+        let line = disassemble_at(memory, addr);
+        addr += line.words.len();
+        lines.push(line);
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn listing() {
+        let memory = [1002, 4, 3, 4, 33, 109, -7, 21101, 5, 6, 3, 99, 3];
+        let text: Vec<_> = disassemble(&memory).iter().map(Line::text).collect();
+        assert_eq!(
+            text,
+            [
+                "MUL [4], #3, [4]",
+                ".data 33",
+                "ARB #-7",
+                "ADD #5, #6, [rb+3]",
+                "HALT",
+                ".data 3",
+            ]
+        );
+        assert_eq!(
+            disassemble_at(&memory, 0).to_string(),
+            "    0: 1002 4 3 4               MUL [4], #3, [4]"
+        );
+        assert_eq!(disassemble_at(&[204, -2], 0).text(), "OUT [rb-2]");
+
+        // words that would assemble differently are data
+        assert_eq!(disassemble_at(&[10099], 0).text(), ".data 10099");
+        assert_eq!(disassemble_at(&[11101, 1, 2, 3], 0).text(), ".data 11101");
+        assert_eq!(disassemble_at(&[103, 5], 0).text(), ".data 103");
+    }
+
+    #[test]
+    fn end_of_address_space() {
+        // an instruction whose parameters would wrap around is data
+        struct Top;
+        impl Words for Top {
+            fn word(&self, addr: usize) -> Option<i64> {
+                Some(if addr == usize::MAX - 1 { 1 } else { 99 })
+            }
+        }
+        assert_eq!(disassemble_at(&Top, usize::MAX - 1).text(), ".data 1");
+        assert_eq!(disassemble_at(&Top, usize::MAX).text(), "HALT");
+    }
+}
@@ -0,0 +1,133 @@
+use std::{error::Error, fmt, io};
+
+/// An error raised while running an Intcode program over words of type `W`.
+#[derive(Debug)]
+pub enum IntcodeError<W = i64> {
+    /// The instruction at `ip` is negative, or too large to decode.
+    InvalidInstruction { ip: usize, instruction: W },
+    /// The instruction at `ip` has an opcode the VM does not know.
+    UnknownOpcode {
+        ip: usize,
+        instruction: W,
+        opcode: i64,
+    },
+    /// The instruction at `ip` has a parameter mode the VM does not know.
+    UnknownParamMode {
+        ip: usize,
+        instruction: W,
+        mode: i64,
+    },
+    /// The instruction at `ip` tried to read or write a negative address.
+    NegativeAddress { ip: usize, instruction: W, addr: W },
+    /// The instruction at `ip` used an address too large for memory.
+    AddressTooLarge { ip: usize, instruction: W, addr: W },
+    /// The instruction at `ip` tried to jump to a negative or too large
+    /// address.
+    InvalidJump {
+        ip: usize,
+        instruction: W,
+        target: W,
+    },
+    /// The instruction at `ip` needs input but none is left.
+    MissingInput { ip: usize },
+    /// Reading input for the instruction at `ip` failed.
+    Input { ip: usize, source: io::Error },
+    /// Writing output from the instruction at `ip` failed.
+    Output { ip: usize, source: io::Error },
+    /// Arithmetic in the instruction at `ip` overflowed under
+    /// [`Overflow::Checked`](crate::Overflow::Checked).
+    Overflow { ip: usize, instruction: W },
+    /// The machine state at `ip` came round again after `period` steps with
+    /// no input or output, so the program can never halt.
+    InfiniteLoop { ip: usize, period: u64 },
+    /// A [custom opcode](crate::custom) handler failed.
+    Custom {
+        ip: usize,
+        instruction: W,
+        message: String,
+    },
+}
+
+impl<W: fmt::Display> fmt::Display for IntcodeError<W> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IntcodeError::InvalidInstruction { ip, instruction } => {
+                write!(f, "invalid instruction {instruction} at address {ip}")
+            }
+            IntcodeError::UnknownOpcode {
+                ip,
+                instruction,
+                opcode,
+            } => write!(
+                f,
+                "unknown opcode {opcode} in instruction {instruction} at address {ip}"
+            ),
+            IntcodeError::UnknownParamMode {
+                ip,
+                instruction,
+                mode,
+            } => write!(
+                f,
+                "unknown parameter mode {mode} in instruction {instruction} at address {ip}"
+            ),
+            IntcodeError::NegativeAddress {
+                ip,
+                instruction,
+                addr,
+            } => write!(
+                f,
+                "negative address {addr} used by instruction {instruction} at address {ip}"
+            ),
+            IntcodeError::AddressTooLarge {
+                ip,
+                instruction,
+                addr,
+            } => write!(
+                f,
+                "address {addr} too large in instruction {instruction} at address {ip}"
+            ),
+            IntcodeError::InvalidJump {
+                ip,
+                instruction,
+                target,
+            } => write!(
+                f,
+                "jump to invalid address {target} by instruction {instruction} at address {ip}"
+            ),
+            IntcodeError::MissingInput { ip } => {
+                write!(f, "no input left for instruction at address {ip}")
+            }
+            IntcodeError::Input { ip, source } => {
+                write!(f, "failed to read input at address {ip}: {source}")
+            }
+            IntcodeError::Output { ip, source } => {
+                write!(f, "failed to write output at address {ip}: {source}")
+            }
+            IntcodeError::Overflow { ip, instruction } => {
+                write!(f, "overflow in instruction {instruction} at address {ip}")
+            }
+            IntcodeError::InfiniteLoop { ip, period } => {
+                write!(f, "infinite loop of {period} steps at address {ip}")
+            }
+            IntcodeError::Custom {
+                ip,
+                instruction,
+                message,
+            } => write!(
+                f,
+                "instruction {instruction} at address {ip} failed: {message}"
+            ),
+        }
+    }
+}
+
+impl<W: fmt::Debug + fmt::Display> Error for IntcodeError<W> {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            IntcodeError::Input { source, .. } | IntcodeError::Output { source, .. } => {
+                Some(source)
+            }
+            _ => None,
+        }
+    }
+}
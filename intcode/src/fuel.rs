@@ -0,0 +1,195 @@
+use crate::{IntcodeError, IntcodeInput, IntcodeOutput, Memory, Program, StepResult, Word};
+
+/// How a [`Program::run_with_fuel`] call ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunOutcome {
+    /// The program reached a halt instruction.
+    Halted,
+    /// The step budget ran out first. The program can be resumed by running
+    /// it again.
+    OutOfFuel,
+}
+
+/// Finds repeated machine states with Brent's cycle detection: the state is
+/// saved at power of two step counts and every later state compared to it.
+#[derive(Debug, Clone)]
+pub(crate) struct LoopDetector<W> {
+    ip: usize,
+    relative_base: W,
+    memory: Memory<W>,
+    steps: u64,
+    power: u64,
+}
+
+impl<W: Word> LoopDetector<W> {
+    fn new(program: &Program<W>) -> LoopDetector<W> {
+        LoopDetector {
+            ip: program.ip,
+            relative_base: program.relative_base.clone(),
+            memory: program.memory.clone(),
+            steps: 0,
+            power: 1,
+        }
+    }
+
+    fn matches(&self, program: &Program<W>) -> bool {
+        self.ip == program.ip
+            && self.relative_base == program.relative_base
+            && self.memory == program.memory
+    }
+}
+
+impl<W: Word> Program<W> {
+    /// Run like [`Program::run`], but stop after executing `max_steps`
+    /// instructions.
+    pub fn run_with_fuel<I, O>(
+        &mut self,
+        mut input: I,
+        mut output: O,
+        max_steps: u64,
+    ) -> Result<RunOutcome, IntcodeError<W>>
+    where
+        I: IntcodeInput<W>,
+        O: IntcodeOutput<W>,
+    {
+        let mut steps = 0;
+        while steps < max_steps {
+            let ip = self.ip;
+            match self.step()? {
+                StepResult::Continue => {}
+                StepResult::NeedsInput => {
+                    let value = input
+                        .read_input()
+                        .map_err(|source| IntcodeError::Input {
+                            ip: self.ip,
+                            source,
+                        })?
+                        .ok_or(IntcodeError::MissingInput { ip: self.ip })?;
+                    self.push_input(value);
+                    // nothing was executed
+                    continue;
+                }
+                StepResult::Output(value) => self.write_output(&mut output, ip, value)?,
+                StepResult::Halted => return Ok(RunOutcome::Halted),
+            }
+            steps += 1;
+        }
+        Ok(RunOutcome::OutOfFuel)
+    }
+
+    /// Fail with [`IntcodeError::InfiniteLoop`] when the exact machine state
+    /// (instruction pointer, relative base and memory) repeats without any
+    /// input or output in between.
+    ///
+    /// A program that loops through `n` states is reported within about `3n`
+    /// steps of entering the loop.
+    pub fn enable_loop_detection(&mut self) {
+        self.loop_detector = Some(Box::new(LoopDetector::new(self)));
+    }
+
+    pub fn disable_loop_detection(&mut self) {
+        self.loop_detector = None;
+    }
+
+    pub(crate) fn step_detecting_loops(&mut self) -> Result<StepResult<W>, IntcodeError<W>> {
+        let inputs = self.inputs.len();
+        let result = self.step_profiled_if_enabled()?;
+        let io = matches!(result, StepResult::Output(_)) || self.inputs.len() < inputs;
+        if io || result != StepResult::Continue {
+            self.loop_detector = Some(Box::new(LoopDetector::new(self)));
+            return Ok(result);
+        }
+        let Some(mut detector) = self.loop_detector.take() else {
+            return Ok(result);
+        };
+        detector.steps += 1;
+        let period = detector.steps;
+        let repeated = detector.matches(self);
+        if !repeated && detector.steps == detector.power {
+            *detector = LoopDetector {
+                power: detector.power * 2,
+                ..LoopDetector::new(self)
+            };
+        }
+        self.loop_detector = Some(detector);
+        if repeated {
+            return Err(IntcodeError::InfiniteLoop {
+                ip: self.ip,
+                period,
+            });
+        }
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm;
+
+    #[test]
+    fn resume_after_fuel_runs_out() {
+        let memory = asm::assemble(
+            "
+                    IN [n]
+            loop:   OUT [n]
+                    ADD [n], #-1, [n]
+                    JT [n], #loop
+                    HALT
+            n:      .data 0
+            ",
+        )
+        .unwrap();
+        let mut program = Program::new(memory.clone());
+        let mut output = Vec::new();
+        let outcome = program.run_with_fuel(Some(5), &mut output, 4).unwrap();
+        assert_eq!(outcome, RunOutcome::OutOfFuel);
+        assert_eq!(output, [5]);
+        assert_eq!(program.ip(), 2);
+
+        let outcome = program.run_with_fuel(None, &mut output, 100).unwrap();
+        assert_eq!(outcome, RunOutcome::Halted);
+        assert_eq!(output, [5, 4, 3, 2, 1]);
+
+        // running out exactly at the halt still needs another call to see it
+        let mut program = Program::new(memory);
+        let outcome = program.run_with_fuel(Some(1), Vec::new(), 4).unwrap();
+        assert_eq!(outcome, RunOutcome::OutOfFuel);
+        let outcome = program.run_with_fuel(None, Vec::new(), 0).unwrap();
+        assert_eq!(outcome, RunOutcome::OutOfFuel);
+        let outcome = program.run_with_fuel(None, Vec::new(), 1).unwrap();
+        assert_eq!(outcome, RunOutcome::Halted);
+    }
+
+    #[test]
+    fn infinite_loops() {
+        // spins forever, flipping a flag each time round
+        let memory = asm::assemble(
+            "
+                    OUT #1
+            loop:   EQ [flag], #0, [flag]
+                    JT #1, #loop
+            flag:   .data 0
+            ",
+        )
+        .unwrap();
+        let mut program = Program::new(memory);
+        program.enable_loop_detection();
+        let mut output = Vec::new();
+        let err = program.run(None, &mut output).unwrap_err();
+        assert_eq!(err.to_string(), "infinite loop of 4 steps at address 6");
+        assert_eq!(output, [1]);
+
+        // a loop that outputs each time round isn't infinite without I/O
+        let mut program = Program::new(vec![104, 7, 1105, 1, 0]);
+        program.enable_loop_detection();
+        let outcome = program.run_with_fuel(None, Vec::new(), 1000).unwrap();
+        assert_eq!(outcome, RunOutcome::OutOfFuel);
+
+        // nor is a counter, which never repeats a state
+        let mut program = Program::new(vec![1001, 5, 1, 5, 1105, 1, 0]);
+        program.enable_loop_detection();
+        let outcome = program.run_with_fuel(None, Vec::new(), 10_000).unwrap();
+        assert_eq!(outcome, RunOutcome::OutOfFuel);
+    }
+}
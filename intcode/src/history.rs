@@ -0,0 +1,127 @@
+use crate::{trace::TraceEntry, Instruction, Opcode, Program, Word};
+
+/// What is needed to undo one executed instruction.
+#[derive(Clone)]
+pub(crate) struct Undo<W> {
+    pub(crate) entry: TraceEntry<W>,
+    /// The memory length before the instruction, which may have grown it.
+    pub(crate) memory_len: usize,
+}
+
+impl<W: Word> Program<W> {
+    /// Start keeping undo records so execution can be stepped backwards.
+    /// Only instructions executed from now on can be undone.
+    pub fn enable_history(&mut self) {
+        if self.history.is_none() {
+            self.history = Some(Vec::new());
+        }
+    }
+
+    /// Stop keeping undo records and discard the ones kept so far.
+    pub fn disable_history(&mut self) {
+        self.history = None;
+    }
+
+    /// The number of instructions that can be undone.
+    #[must_use]
+    pub fn history_len(&self) -> usize {
+        self.history.as_ref().map_or(0, Vec::len)
+    }
+
+    /// Undo the most recently executed instruction, restoring the memory it
+    /// wrote, the relative base, the instruction pointer and any input it
+    /// consumed. Returns false if there is nothing to undo.
+    pub fn step_back(&mut self) -> bool {
+        let Some(Undo { entry, memory_len }) = self.history.as_mut().and_then(Vec::pop) else {
+            return false;
+        };
+        let opcode = entry
+            .instruction
+            .to_i64()
+            .and_then(|word| Instruction::decode(word).ok())
+            .map(|i| i.opcode);
+        for write in entry.writes.into_iter().rev() {
+            self.write(write.addr, write.old);
+            if opcode == Some(Opcode::Input) {
+                self.inputs.push_front(write.new);
+            }
+        }
+        self.memory.truncate(memory_len);
+        self.clear_cache();
+        if let Some((relative_base, _)) = entry.relative_base {
+            self.relative_base = relative_base;
+        }
+        self.ip = entry.ip;
+        true
+    }
+
+    /// Step backwards until the instruction at `addr` is about to run again.
+    /// Returns false if the history runs out first.
+    pub fn run_back_to(&mut self, addr: usize) -> bool {
+        while self.step_back() {
+            if self.ip == addr {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Step backwards until the most recent instruction that wrote to memory
+    /// at `addr` is about to run again. Returns false if the history runs out
+    /// first.
+    pub fn run_back_to_write(&mut self, addr: usize) -> bool {
+        loop {
+            let wrote = match self.history.as_ref().and_then(|history| history.last()) {
+                Some(undo) => undo.entry.writes.iter().any(|write| write.addr == addr),
+                None => return false,
+            };
+            self.step_back();
+            if wrote {
+                return true;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{asm, Program, StepResult};
+
+    #[test]
+    fn step_back() {
+        let memory = asm::assemble(
+            "
+                    ARB #10
+                    IN [x]
+                    MUL [x], #3, [y]
+                    ADD [y], #1, [rb+20]
+                    OUT [rb+20]
+                    HALT
+            x:      .data 0
+            y:      .data 0
+            ",
+        )
+        .unwrap();
+        let mut program = Program::new(memory.clone());
+        program.enable_history();
+        program.push_input(4);
+        assert_eq!(program.run_until_event().unwrap(), StepResult::Output(13));
+        assert_eq!(program.history_len(), 5);
+        assert_eq!(program.memory()[30], 13);
+
+        // walk back from the output to the instruction that computed y
+        let y = memory.len() - 1;
+        assert!(program.run_back_to_write(y));
+        assert_eq!(program.ip(), 4);
+        assert_eq!(program.memory()[y], 0);
+
+        assert!(program.run_back_to(0));
+        assert_eq!(program.ip(), 0);
+        assert_eq!(program.relative_base(), 0);
+        assert_eq!(program.memory().to_vec(), memory);
+        assert!(!program.step_back());
+
+        // the consumed input was put back
+        assert_eq!(program.run_until_event().unwrap(), StepResult::Output(13));
+    }
+}
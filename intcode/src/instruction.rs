@@ -0,0 +1,195 @@
+use crate::IntcodeError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ParamMode {
+    Position,
+    Immediate,
+    Relative,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Opcode {
+    Add,
+    Multiply,
+    Input,
+    Output,
+    JumpIfTrue,
+    JumpIfFalse,
+    LessThan,
+    Equals,
+    AdjustRelativeBase,
+    Halt,
+}
+
+impl Opcode {
+    pub fn from_code(code: i64) -> Option<Opcode> {
+        let opcode = match code {
+            1 => Opcode::Add,
+            2 => Opcode::Multiply,
+            3 => Opcode::Input,
+            4 => Opcode::Output,
+            5 => Opcode::JumpIfTrue,
+            6 => Opcode::JumpIfFalse,
+            7 => Opcode::LessThan,
+            8 => Opcode::Equals,
+            9 => Opcode::AdjustRelativeBase,
+            99 => Opcode::Halt,
+            _ => return None,
+        };
+        Some(opcode)
+    }
+
+    #[must_use]
+    pub fn code(self) -> i64 {
+        match self {
+            Opcode::Add => 1,
+            Opcode::Multiply => 2,
+            Opcode::Input => 3,
+            Opcode::Output => 4,
+            Opcode::JumpIfTrue => 5,
+            Opcode::JumpIfFalse => 6,
+            Opcode::LessThan => 7,
+            Opcode::Equals => 8,
+            Opcode::AdjustRelativeBase => 9,
+            Opcode::Halt => 99,
+        }
+    }
+
+    #[must_use]
+    pub fn mnemonic(self) -> &'static str {
+        match self {
+            Opcode::Add => "ADD",
+            Opcode::Multiply => "MUL",
+            Opcode::Input => "IN",
+            Opcode::Output => "OUT",
+            Opcode::JumpIfTrue => "JT",
+            Opcode::JumpIfFalse => "JF",
+            Opcode::LessThan => "LT",
+            Opcode::Equals => "EQ",
+            Opcode::AdjustRelativeBase => "ARB",
+            Opcode::Halt => "HALT",
+        }
+    }
+
+    pub fn from_mnemonic(mnemonic: &str) -> Option<Opcode> {
+        let opcode = match mnemonic.to_ascii_uppercase().as_str() {
+            "ADD" => Opcode::Add,
+            "MUL" => Opcode::Multiply,
+            "IN" => Opcode::Input,
+            "OUT" => Opcode::Output,
+            "JT" => Opcode::JumpIfTrue,
+            "JF" => Opcode::JumpIfFalse,
+            "LT" => Opcode::LessThan,
+            "EQ" => Opcode::Equals,
+            "ARB" => Opcode::AdjustRelativeBase,
+            "HALT" => Opcode::Halt,
+            _ => return None,
+        };
+        Some(opcode)
+    }
+
+    #[must_use]
+    pub fn param_count(self) -> usize {
+        match self {
+            Opcode::Add | Opcode::Multiply | Opcode::LessThan | Opcode::Equals => 3,
+            Opcode::JumpIfTrue | Opcode::JumpIfFalse => 2,
+            Opcode::Input | Opcode::Output | Opcode::AdjustRelativeBase => 1,
+            Opcode::Halt => 0,
+        }
+    }
+
+    /// The index of the parameter this opcode writes to, if any.
+    #[must_use]
+    pub fn write_param(self) -> Option<usize> {
+        match self {
+            Opcode::Add | Opcode::Multiply | Opcode::LessThan | Opcode::Equals => Some(2),
+            Opcode::Input => Some(0),
+            _ => None,
+        }
+    }
+}
+
+/// Why an instruction word could not be decoded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecodeError {
+    Negative,
+    UnknownOpcode(i64),
+    UnknownParamMode(i64),
+}
+
+impl DecodeError {
+    pub(crate) fn at<W>(self, ip: usize, instruction: W) -> IntcodeError<W> {
+        match self {
+            DecodeError::Negative => IntcodeError::InvalidInstruction { ip, instruction },
+            DecodeError::UnknownOpcode(opcode) => IntcodeError::UnknownOpcode {
+                ip,
+                instruction,
+                opcode,
+            },
+            DecodeError::UnknownParamMode(mode) => IntcodeError::UnknownParamMode {
+                ip,
+                instruction,
+                mode,
+            },
+        }
+    }
+}
+
+/// A decoded instruction word.
+///
+/// Only the modes of parameters the opcode actually takes are decoded, any
+/// higher digits are ignored. Modes of missing parameters are
+/// [`ParamMode::Position`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Instruction {
+    pub opcode: Opcode,
+    pub modes: [ParamMode; 3],
+}
+
+impl Instruction {
+    pub fn decode(word: i64) -> Result<Instruction, DecodeError> {
+        if word < 0 {
+            return Err(DecodeError::Negative);
+        }
+        let opcode = Opcode::from_code(word % 100).ok_or(DecodeError::UnknownOpcode(word % 100))?;
+        let mut modes = [ParamMode::Position; 3];
+        let mut digits = word / 100;
+        for mode in modes.iter_mut().take(opcode.param_count()) {
+            *mode = match digits % 10 {
+                0 => ParamMode::Position,
+                1 => ParamMode::Immediate,
+                2 => ParamMode::Relative,
+                digit => return Err(DecodeError::UnknownParamMode(digit)),
+            };
+            digits /= 10;
+        }
+        Ok(Instruction { opcode, modes })
+    }
+
+    /// Encode back into an instruction word.
+    #[must_use]
+    pub fn encode(&self) -> i64 {
+        let mut word = 0;
+        for mode in self.modes.iter().rev() {
+            word = word * 10
+                + match mode {
+                    ParamMode::Position => 0,
+                    ParamMode::Immediate => 1,
+                    ParamMode::Relative => 2,
+                };
+        }
+        word * 100 + self.opcode.code()
+    }
+
+    /// The number of memory words taken by the instruction and its parameters.
+    #[must_use]
+    pub fn size(&self) -> usize {
+        1 + self.opcode.param_count()
+    }
+
+    /// The modes of the parameters this instruction takes.
+    #[must_use]
+    pub fn param_modes(&self) -> &[ParamMode] {
+        &self.modes[..self.opcode.param_count()]
+    }
+}
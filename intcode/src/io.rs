@@ -0,0 +1,161 @@
+use std::{
+    collections::VecDeque,
+    io::{self, BufRead, Write},
+    sync::mpsc::{Receiver, Sender},
+};
+
+use crate::Word;
+
+/// A source of input values for a [`Program`](crate::Program) over words of
+/// type `W`.
+pub trait IntcodeInput<W = i64> {
+    /// Take the next input value, or `None` if no more input is available.
+    fn read_input(&mut self) -> io::Result<Option<W>>;
+}
+
+/// A sink for output values from a [`Program`](crate::Program) over words of
+/// type `W`.
+pub trait IntcodeOutput<W = i64> {
+    fn write_output(&mut self, value: W) -> io::Result<()>;
+}
+
+impl<W, T: IntcodeInput<W> + ?Sized> IntcodeInput<W> for &mut T {
+    fn read_input(&mut self) -> io::Result<Option<W>> {
+        (**self).read_input()
+    }
+}
+
+impl<W, T: IntcodeOutput<W> + ?Sized> IntcodeOutput<W> for &mut T {
+    fn write_output(&mut self, value: W) -> io::Result<()> {
+        (**self).write_output(value)
+    }
+}
+
+impl<W: Word> IntcodeInput<W> for VecDeque<W> {
+    fn read_input(&mut self) -> io::Result<Option<W>> {
+        Ok(self.pop_front())
+    }
+}
+
+impl<W: Word> IntcodeOutput<W> for VecDeque<W> {
+    fn write_output(&mut self, value: W) -> io::Result<()> {
+        self.push_back(value);
+        Ok(())
+    }
+}
+
+/// A single input value, taken on first read.
+impl<W: Word> IntcodeInput<W> for Option<W> {
+    fn read_input(&mut self) -> io::Result<Option<W>> {
+        Ok(self.take())
+    }
+}
+
+impl<W: Word> IntcodeOutput<W> for Vec<W> {
+    fn write_output(&mut self, value: W) -> io::Result<()> {
+        self.push(value);
+        Ok(())
+    }
+}
+
+/// Block until a value arrives, or return `None` once every sender is gone.
+impl<W: Word> IntcodeInput<W> for Receiver<W> {
+    fn read_input(&mut self) -> io::Result<Option<W>> {
+        Ok(self.recv().ok())
+    }
+}
+
+impl<W: Word> IntcodeOutput<W> for Sender<W> {
+    fn write_output(&mut self, value: W) -> io::Result<()> {
+        self.send(value)
+            .map_err(|err| io::Error::new(io::ErrorKind::BrokenPipe, err))
+    }
+}
+
+/// Input taken from a closure, see [`input_fn`].
+pub struct InputFn<F>(F);
+
+/// Create an input that calls `f` each time the program needs a value.
+pub fn input_fn<W, F: FnMut() -> Option<W>>(f: F) -> InputFn<F> {
+    InputFn(f)
+}
+
+impl<W, F: FnMut() -> Option<W>> IntcodeInput<W> for InputFn<F> {
+    fn read_input(&mut self) -> io::Result<Option<W>> {
+        Ok((self.0)())
+    }
+}
+
+/// Input taken from an iterator of values.
+pub struct IterInput<I>(I);
+
+impl<I: Iterator> IterInput<I> {
+    pub fn new<T: IntoIterator<IntoIter = I>>(values: T) -> IterInput<I> {
+        IterInput(values.into_iter())
+    }
+}
+
+impl<W, I: Iterator<Item = W>> IntcodeInput<W> for IterInput<I> {
+    fn read_input(&mut self) -> io::Result<Option<W>> {
+        Ok(self.0.next())
+    }
+}
+
+/// Output passed to a closure, see [`output_fn`].
+pub struct OutputFn<F>(F);
+
+/// Create an output that calls `f` with each value the program outputs.
+pub fn output_fn<W, F: FnMut(W)>(f: F) -> OutputFn<F> {
+    OutputFn(f)
+}
+
+impl<W, F: FnMut(W)> IntcodeOutput<W> for OutputFn<F> {
+    fn write_output(&mut self, value: W) -> io::Result<()> {
+        (self.0)(value);
+        Ok(())
+    }
+}
+
+/// Input parsed from a text stream with one decimal number per line.
+pub struct TextInput<R>(R);
+
+impl<R: BufRead> TextInput<R> {
+    pub fn new(reader: R) -> TextInput<R> {
+        TextInput(reader)
+    }
+}
+
+impl<W: Word, R: BufRead> IntcodeInput<W> for TextInput<R> {
+    fn read_input(&mut self) -> io::Result<Option<W>> {
+        let mut buf = String::new();
+        if self.0.read_line(&mut buf)? == 0 {
+            return Ok(None);
+        }
+        let line = buf.trim();
+        line.parse().map(Some).map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("invalid input '{line}'"),
+            )
+        })
+    }
+}
+
+/// Output written to a text stream with one decimal number per line.
+pub struct TextOutput<T>(T);
+
+impl<T: Write> TextOutput<T> {
+    pub fn new(writer: T) -> TextOutput<T> {
+        TextOutput(writer)
+    }
+
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<W: Word, T: Write> IntcodeOutput<W> for TextOutput<T> {
+    fn write_output(&mut self, value: W) -> io::Result<()> {
+        writeln!(self.0, "{value}")
+    }
+}
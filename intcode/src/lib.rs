@@ -1,210 +1,622 @@
-use std::{
-    fs,
-    io::{self, BufRead, Write},
-    path::Path,
-};
-
-pub fn read_program_file<T: AsRef<Path>>(file_path: T) -> io::Result<Vec<i64>> {
-    let memory = fs::read_to_string(file_path)?
-        .trim()
-        .split(',')
-        .map(|x| x.parse().unwrap())
-        .collect();
-    Ok(memory)
-}
+use std::{collections::VecDeque, fs, path::Path};
+
+pub mod arcade;
+pub mod ascii;
+pub mod asm;
+mod bigint;
+mod cache;
+pub mod cfg;
+pub mod custom;
+pub mod debugger;
+pub mod disasm;
+mod error;
+mod fuel;
+mod history;
+mod instruction;
+pub mod io;
+mod memory;
+pub mod network;
+mod outputs;
+mod overflow;
+pub mod profile;
+mod scheduler;
+pub mod snapshot;
+pub mod topology;
+pub mod trace;
+pub mod transpile;
+mod word;
 
-#[derive(PartialEq)]
-enum ParamMode {
-    Position,
-    Immediate,
-    Relative,
+pub use bigint::{BigInt, ParseBigIntError};
+pub use error::IntcodeError;
+pub use fuel::RunOutcome;
+pub use instruction::{DecodeError, Instruction, Opcode, ParamMode};
+pub use io::{IntcodeInput, IntcodeOutput};
+pub use memory::{Memory, MemoryKind};
+pub use outputs::Outputs;
+pub use overflow::Overflow;
+pub use scheduler::{Scheduler, SchedulerError};
+pub use word::Word;
+
+pub fn read_program_file<W: Word, T: AsRef<Path>>(file_path: T) -> std::io::Result<Vec<W>> {
+    parse_values(fs::read_to_string(file_path)?.trim())
 }
 
-struct ParamModes(u32);
-
-impl ParamModes {
-    fn next(&mut self) -> ParamMode {
-        let mode = self.0 % 10;
-        self.0 /= 10;
-        match mode {
-            0 => ParamMode::Position,
-            1 => ParamMode::Immediate,
-            2 => ParamMode::Relative,
-            _ => panic!("unknown parameter mode {mode}"),
-        }
+/// Parse comma separated values, the format of program files.
+pub(crate) fn parse_values<W: Word>(text: &str) -> std::io::Result<Vec<W>> {
+    if text.is_empty() {
+        return Ok(Vec::new());
     }
+    text.split(',')
+        .map(|x| {
+            x.parse()
+                .map_err(|_| invalid_data(format!("invalid value '{x}'")))
+        })
+        .collect()
+}
+
+pub(crate) fn invalid_data(message: impl Into<String>) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, message.into())
+}
+
+pub(crate) fn format_values<'a, W: Word, I: IntoIterator<Item = &'a W>>(values: I) -> String {
+    let values: Vec<_> = values.into_iter().map(W::to_string).collect();
+    values.join(",")
 }
 
+/// The outcome of executing a single instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StepResult<W = i64> {
+    /// An instruction ran and the program can keep going.
+    Continue,
+    /// The next instruction is an input but the input queue is empty.
+    NeedsInput,
+    /// An output instruction produced this value.
+    Output(W),
+    /// The program has reached a halt instruction.
+    Halted,
+}
+
+/// An Intcode machine over words of type `W`.
+///
+/// Most programs use the default `i64` words, which [`Program::new`] and
+/// [`Program::from_file`] create. Other word types are created with
+/// [`Program::with_memory_kind`] or `From<Vec<W>>`.
 #[derive(Clone)]
-pub struct Program {
+pub struct Program<W = i64> {
     ip: usize,
-    relative_base: usize,
-    memory: Vec<i64>,
+    relative_base: W,
+    memory: Memory<W>,
+    inputs: VecDeque<W>,
+    trace: Option<Box<trace::Trace<W>>>,
+    history: Option<Vec<history::Undo<W>>>,
+    cache: Option<std::sync::Arc<cache::DecodeCache<W>>>,
+    profile: Option<Box<profile::Profile>>,
+    loop_detector: Option<Box<fuel::LoopDetector<W>>>,
+    overflow: Overflow,
+    custom: Option<custom::CustomOpcodes<W>>,
+    /// Writes made by the running custom opcode, kept while recording.
+    custom_writes: Vec<trace::MemoryWrite<W>>,
 }
 
 impl Program {
     #[must_use]
     pub fn new(memory: Vec<i64>) -> Program {
-        Program {
-            ip: 0,
-            relative_base: 0,
-            memory,
-        }
+        Program::with_memory_kind(memory, MemoryKind::default())
     }
 
-    pub fn from_file<T: AsRef<Path>>(file_path: T) -> io::Result<Program> {
+    pub fn from_file<T: AsRef<Path>>(file_path: T) -> std::io::Result<Program> {
         let memory = read_program_file(file_path)?;
         Ok(Self::new(memory))
     }
+}
+
+impl<W: Word> From<Vec<W>> for Program<W> {
+    fn from(memory: Vec<W>) -> Program<W> {
+        Program::with_memory_kind(memory, MemoryKind::default())
+    }
+}
+
+impl<W: Word> Program<W> {
+    /// Create a program whose memory is stored as `kind`.
+    #[must_use]
+    pub fn with_memory_kind(memory: Vec<W>, kind: MemoryKind) -> Program<W> {
+        Program {
+            ip: 0,
+            relative_base: W::default(),
+            memory: Memory::new(memory, kind),
+            inputs: VecDeque::new(),
+            trace: None,
+            history: None,
+            cache: None,
+            profile: None,
+            loop_detector: None,
+            overflow: Overflow::default(),
+            custom: None,
+            custom_writes: Vec::new(),
+        }
+    }
 
     #[must_use]
-    pub fn memory(&self) -> &[i64] {
+    pub fn memory(&self) -> &Memory<W> {
         &self.memory
     }
 
-    pub fn run<R, W>(&mut self, mut reader: R, mut writer: W)
+    #[must_use]
+    pub fn ip(&self) -> usize {
+        self.ip
+    }
+
+    #[must_use]
+    pub fn relative_base(&self) -> W {
+        self.relative_base.clone()
+    }
+
+    /// Overwrite the value at `addr`, growing memory if needed.
+    pub fn poke(&mut self, addr: usize, value: W) {
+        self.write(addr, value);
+    }
+
+    /// Queue a value for the next input instruction.
+    pub fn push_input(&mut self, value: W) {
+        self.inputs.push_back(value);
+    }
+
+    /// The queue of values waiting to be read by input instructions.
+    pub fn inputs_mut(&mut self) -> &mut VecDeque<W> {
+        &mut self.inputs
+    }
+
+    /// Run until the program halts, reading from `input` whenever the input
+    /// queue is empty.
+    pub fn run<I, O>(&mut self, mut input: I, mut output: O) -> Result<(), IntcodeError<W>>
     where
-        R: BufRead,
-        W: Write,
+        I: IntcodeInput<W>,
+        O: IntcodeOutput<W>,
     {
-        while self.memory[self.ip] != 99 {
-            self.execute_instruction(&mut reader, &mut writer);
+        loop {
+            match self.next_event()? {
+                (_, StepResult::NeedsInput) => {
+                    let value = input
+                        .read_input()
+                        .map_err(|source| IntcodeError::Input {
+                            ip: self.ip,
+                            source,
+                        })?
+                        .ok_or(IntcodeError::MissingInput { ip: self.ip })?;
+                    self.push_input(value);
+                }
+                (ip, StepResult::Output(value)) => self.write_output(&mut output, ip, value)?,
+                (_, StepResult::Halted) => return Ok(()),
+                (_, StepResult::Continue) => unreachable!(),
+            }
         }
     }
 
     /// Run with provided input until program halts or requires more input.
     /// Returns true if program halts or false if program requires more input.
-    pub fn run_with_input<W: Write>(&mut self, input: i64, mut writer: W) -> bool {
-        let reader = input.to_string().into_bytes();
-        let mut input_used = false;
+    pub fn run_with_input<O: IntcodeOutput<W>>(
+        &mut self,
+        input: W,
+        mut output: O,
+    ) -> Result<bool, IntcodeError<W>> {
+        self.push_input(input);
         loop {
-            let instruction = self.memory[self.ip];
-            if instruction == 99 {
-                return true;
-            } else if instruction == 3 {
-                if input_used {
-                    return false;
-                }
-                input_used = true;
+            match self.next_event()? {
+                (_, StepResult::NeedsInput) => return Ok(false),
+                (ip, StepResult::Output(value)) => self.write_output(&mut output, ip, value)?,
+                (_, StepResult::Halted) => return Ok(true),
+                (_, StepResult::Continue) => unreachable!(),
             }
-            self.execute_instruction(reader.as_slice(), &mut writer);
         }
     }
 
-    fn get_param(&self, offset: usize, mode: ParamMode) -> i64 {
-        let mut value = self.memory[self.ip + offset];
+    /// Lazily run the program, yielding each output and taking values from
+    /// `inputs` whenever the program needs input.
+    pub fn outputs<I>(&mut self, inputs: I) -> Outputs<'_, io::IterInput<I::IntoIter>, W>
+    where
+        I: IntoIterator<Item = W>,
+    {
+        Outputs::new(self, io::IterInput::new(inputs))
+    }
+
+    /// Lazily run the program, yielding each output and calling `f` whenever
+    /// the program needs input.
+    pub fn outputs_with<F>(&mut self, f: F) -> Outputs<'_, io::InputFn<F>, W>
+    where
+        F: FnMut() -> Option<W>,
+    {
+        Outputs::new(self, io::input_fn(f))
+    }
+
+    /// Step until something other than [`StepResult::Continue`] happens.
+    pub fn run_until_event(&mut self) -> Result<StepResult<W>, IntcodeError<W>> {
+        self.next_event().map(|(_, result)| result)
+    }
+
+    /// Like [`Program::run_until_event`], also returning the address of the
+    /// instruction that caused the event.
+    fn next_event(&mut self) -> Result<(usize, StepResult<W>), IntcodeError<W>> {
+        loop {
+            let ip = self.ip;
+            let result = self.step()?;
+            if result != StepResult::Continue {
+                return Ok((ip, result));
+            }
+        }
+    }
+
+    /// Write a value output by the instruction at `ip`.
+    fn write_output<O: IntcodeOutput<W>>(
+        &self,
+        output: &mut O,
+        ip: usize,
+        value: W,
+    ) -> Result<(), IntcodeError<W>> {
+        output
+            .write_output(value)
+            .map_err(|source| IntcodeError::Output { ip, source })
+    }
+
+    /// Read the value at `addr`, treating memory past the end as zero.
+    fn read(&self, addr: usize) -> W {
+        self.memory.get(addr)
+    }
+
+    /// Decode the instruction at the instruction pointer.
+    fn decode(&self) -> Result<Instruction, IntcodeError<W>> {
+        let instruction = self.read(self.ip);
+        let Some(word) = instruction.to_i64() else {
+            return Err(IntcodeError::InvalidInstruction {
+                ip: self.ip,
+                instruction,
+            });
+        };
+        Instruction::decode(word).map_err(|err| err.at(self.ip, instruction))
+    }
+
+    fn resolve(&self, addr: W) -> Result<usize, IntcodeError<W>> {
+        match addr.to_usize() {
+            Some(addr) => Ok(addr),
+            None if addr.is_negative() => Err(IntcodeError::NegativeAddress {
+                ip: self.ip,
+                instruction: self.read(self.ip),
+                addr,
+            }),
+            None => Err(IntcodeError::AddressTooLarge {
+                ip: self.ip,
+                instruction: self.read(self.ip),
+                addr,
+            }),
+        }
+    }
+
+    fn overflow_error(&self) -> IntcodeError<W> {
+        IntcodeError::Overflow {
+            ip: self.ip,
+            instruction: self.read(self.ip),
+        }
+    }
+
+    fn add_relative_base(&self, value: W) -> Result<W, IntcodeError<W>> {
+        self.overflow
+            .add(value, self.relative_base.clone())
+            .ok_or_else(|| self.overflow_error())
+    }
+
+    fn get_param(&self, offset: usize, mode: ParamMode) -> Result<W, IntcodeError<W>> {
+        let mut value = self.read(self.ip + offset);
         if mode == ParamMode::Immediate {
-            return value;
+            return Ok(value);
         }
         if mode == ParamMode::Relative {
-            value += i64::try_from(self.relative_base).unwrap();
+            value = self.add_relative_base(value)?;
         }
-        let addr: usize = value.try_into().unwrap();
+        let addr = self.resolve(value)?;
 
-        self.memory.get(addr).copied().unwrap_or(0)
+        Ok(self.read(addr))
     }
 
-    fn get_addr(&self, offset: usize, mode: ParamMode) -> usize {
-        let mut addr = self.memory[self.ip + offset];
+    fn get_addr(&self, offset: usize, mode: ParamMode) -> Result<usize, IntcodeError<W>> {
+        let mut addr = self.read(self.ip + offset);
         if mode == ParamMode::Relative {
-            addr += i64::try_from(self.relative_base).unwrap();
+            addr = self.add_relative_base(addr)?;
         }
-        addr.try_into().unwrap()
+        self.resolve(addr)
     }
 
-    fn write(&mut self, addr: usize, value: i64) {
-        if addr >= self.memory.len() {
-            self.memory.resize(addr + 1, 0);
-        }
-        self.memory[addr] = value;
+    fn get_jump_target(&self, offset: usize, mode: ParamMode) -> Result<usize, IntcodeError<W>> {
+        let target = self.get_param(offset, mode)?;
+        target.to_usize().ok_or_else(|| IntcodeError::InvalidJump {
+            ip: self.ip,
+            instruction: self.read(self.ip),
+            target,
+        })
     }
 
-    fn do_binop<F>(&mut self, mut param_modes: ParamModes, f: F)
-    where
-        F: Fn(i64, i64) -> i64,
-    {
-        let param1 = self.get_param(1, param_modes.next());
-        let param2 = self.get_param(2, param_modes.next());
-        let addr = self.get_addr(3, param_modes.next());
-        self.write(addr, f(param1, param2));
+    fn write(&mut self, addr: usize, value: W) {
+        self.invalidate_cache(addr);
+        self.memory.set(addr, value);
     }
 
-    fn execute_instruction<R, W>(&mut self, mut reader: R, mut writer: W)
+    fn do_binop<F>(&mut self, modes: [ParamMode; 3], f: F) -> Result<(), IntcodeError<W>>
     where
-        R: BufRead,
-        W: Write,
+        F: Fn(W, W) -> Option<W>,
     {
-        let instruction: u32 = self.memory[self.ip].try_into().unwrap();
-        let opcode = instruction % 100;
-        let mut param_modes = ParamModes(instruction / 100);
+        let param1 = self.get_param(1, modes[0])?;
+        let param2 = self.get_param(2, modes[1])?;
+        let addr = self.get_addr(3, modes[2])?;
+        let value = f(param1, param2).ok_or_else(|| self.overflow_error())?;
+        self.write(addr, value);
+        Ok(())
+    }
+
+    /// Execute the instruction at the instruction pointer.
+    ///
+    /// Input instructions take values queued with [`Program::push_input`]. If
+    /// the queue is empty, nothing is executed and [`StepResult::NeedsInput`]
+    /// is returned. Stepping a halted program keeps returning
+    /// [`StepResult::Halted`].
+    pub fn step(&mut self) -> Result<StepResult<W>, IntcodeError<W>> {
+        if self.loop_detector.is_some() {
+            return self.step_detecting_loops();
+        }
+        self.step_profiled_if_enabled()
+    }
+
+    fn step_profiled_if_enabled(&mut self) -> Result<StepResult<W>, IntcodeError<W>> {
+        if self.profile.is_some() {
+            return self.step_profiled();
+        }
+        self.step_unprofiled()
+    }
+
+    fn step_unprofiled(&mut self) -> Result<StepResult<W>, IntcodeError<W>> {
+        if self.trace.is_none() && self.history.is_none() {
+            return match self.cache {
+                Some(_) => self.execute_cached(),
+                None => self.execute(),
+            };
+        }
+        let memory_len = self.memory.len();
+        let (result, entry) = self.step_recorded()?;
+        if let Some(entry) = entry {
+            if let Some(trace) = &mut self.trace {
+                trace.push(entry.clone());
+            }
+            if let Some(history) = &mut self.history {
+                history.push(history::Undo { entry, memory_len });
+            }
+        }
+        Ok(result)
+    }
+
+    fn execute(&mut self) -> Result<StepResult<W>, IntcodeError<W>> {
+        let Instruction { opcode, modes } = match self.decode() {
+            Ok(instruction) => instruction,
+            Err(err) => return self.execute_custom(err),
+        };
+        let mut result = StepResult::Continue;
         match opcode {
-            // add
-            1 => {
-                self.do_binop(param_modes, |x, y| x + y);
+            Opcode::Add => {
+                let overflow = self.overflow;
+                self.do_binop(modes, |x, y| overflow.add(x, y))?;
                 self.ip += 4;
             }
-            // multiply
-            2 => {
-                self.do_binop(param_modes, |x, y| x * y);
+            Opcode::Multiply => {
+                let overflow = self.overflow;
+                self.do_binop(modes, |x, y| overflow.mul(x, y))?;
                 self.ip += 4;
             }
-            // input
-            3 => {
-                let addr = self.get_addr(1, param_modes.next());
-                let mut buf = String::new();
-                reader.read_line(&mut buf).unwrap();
-                let input = buf.trim().parse().unwrap();
-                self.write(addr, input);
+            Opcode::Input => {
+                let addr = self.get_addr(1, modes[0])?;
+                let Some(value) = self.inputs.pop_front() else {
+                    return Ok(StepResult::NeedsInput);
+                };
+                self.write(addr, value);
                 self.ip += 2;
             }
-            // output
-            4 => {
-                let param = self.get_param(1, param_modes.next());
-                writeln!(writer, "{param}").unwrap();
+            Opcode::Output => {
+                let param = self.get_param(1, modes[0])?;
+                result = StepResult::Output(param);
                 self.ip += 2;
             }
-            // jump-if-true
-            5 => {
-                let param1 = self.get_param(1, param_modes.next());
-                let param2 = self.get_param(2, param_modes.next());
-                if param1 != 0 {
-                    self.ip = param2.try_into().unwrap();
+            Opcode::JumpIfTrue => {
+                if !self.get_param(1, modes[0])?.is_zero() {
+                    self.ip = self.get_jump_target(2, modes[1])?;
                 } else {
                     self.ip += 3;
                 }
             }
-            // jump-if-false
-            6 => {
-                let param1 = self.get_param(1, param_modes.next());
-                let param2 = self.get_param(2, param_modes.next());
-                if param1 == 0 {
-                    self.ip = param2.try_into().unwrap();
+            Opcode::JumpIfFalse => {
+                if self.get_param(1, modes[0])?.is_zero() {
+                    self.ip = self.get_jump_target(2, modes[1])?;
                 } else {
                     self.ip += 3;
                 }
             }
-            // less than
-            7 => {
-                self.do_binop(param_modes, |x, y| i64::from(x < y));
+            Opcode::LessThan => {
+                self.do_binop(modes, |x, y| Some(W::from_i64(i64::from(x < y))))?;
                 self.ip += 4;
             }
-            // equals
-            8 => {
-                self.do_binop(param_modes, |x, y| i64::from(x == y));
+            Opcode::Equals => {
+                self.do_binop(modes, |x, y| Some(W::from_i64(i64::from(x == y))))?;
                 self.ip += 4;
             }
-            // relative base offset
-            9 => {
-                let value = self.get_param(1, param_modes.next());
-                let abs_value: usize = value.abs().try_into().unwrap();
-                if value.is_negative() {
-                    self.relative_base -= abs_value;
-                } else {
-                    self.relative_base += abs_value;
-                }
+            Opcode::AdjustRelativeBase => {
+                let value = self.get_param(1, modes[0])?;
+                self.relative_base = self.add_relative_base(value)?;
                 self.ip += 2;
             }
-            _ => panic!("Unknown opcode ({opcode}) at address {}", self.ip),
+            Opcode::Halt => result = StepResult::Halted,
         }
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::VecDeque, sync::mpsc};
+
+    use super::*;
+    use io::TextInput;
+
+    fn run_err(memory: Vec<i64>, input: &str) -> IntcodeError {
+        let mut program = Program::new(memory);
+        program
+            .run(TextInput::new(input.as_bytes()), Vec::new())
+            .unwrap_err()
+    }
+
+    #[test]
+    fn errors() {
+        assert!(matches!(
+            run_err(vec![1, 0, 0, 0, 42], ""),
+            IntcodeError::UnknownOpcode {
+                ip: 4,
+                instruction: 42,
+                opcode: 42
+            }
+        ));
+        assert!(matches!(
+            run_err(vec![30001, 0, 0, 0, 99], ""),
+            IntcodeError::UnknownParamMode {
+                ip: 0,
+                instruction: 30001,
+                mode: 3
+            }
+        ));
+        assert!(matches!(
+            run_err(vec![-1, 99], ""),
+            IntcodeError::InvalidInstruction {
+                ip: 0,
+                instruction: -1
+            }
+        ));
+        assert!(matches!(
+            run_err(vec![4, -5, 99], ""),
+            IntcodeError::NegativeAddress {
+                ip: 0,
+                instruction: 4,
+                addr: -5
+            }
+        ));
+        assert!(matches!(
+            run_err(vec![1105, 1, -2, 99], ""),
+            IntcodeError::InvalidJump {
+                ip: 0,
+                instruction: 1105,
+                target: -2
+            }
+        ));
+        assert!(matches!(
+            run_err(vec![3, 0, 99], ""),
+            IntcodeError::MissingInput { ip: 0 }
+        ));
+        assert!(matches!(
+            run_err(vec![3, 0, 99], "abc\n"),
+            IntcodeError::Input { ip: 0, source } if source.kind() == std::io::ErrorKind::InvalidData
+        ));
+    }
+
+    #[test]
+    fn value_io() {
+        // add one to each input until an input of zero
+        let memory = vec![3, 20, 1006, 20, 14, 1001, 20, 1, 21, 4, 21, 1105, 1, 0, 99];
+
+        let mut output = Vec::new();
+        let input = VecDeque::from([1, 41, 0]);
+        Program::new(memory.clone())
+            .run(input, &mut output)
+            .unwrap();
+        assert_eq!(output, [2, 42]);
+
+        let mut inputs = [5, 0].into_iter();
+        let mut output = VecDeque::new();
+        Program::new(memory.clone())
+            .run(io::input_fn(|| inputs.next()), &mut output)
+            .unwrap();
+        assert_eq!(output, [6]);
+
+        let (input_tx, input_rx) = mpsc::channel();
+        let (output_tx, output_rx) = mpsc::channel();
+        input_tx.send(-3).unwrap();
+        input_tx.send(0).unwrap();
+        Program::new(memory).run(input_rx, output_tx).unwrap();
+        assert_eq!(output_rx.iter().collect::<Vec<_>>(), [-2]);
+    }
+
+    #[test]
+    fn run_with_input_relative() {
+        let mut program = Program::new(vec![109, 10, 203, 0, 204, 0, 1105, 1, 2]);
+        let mut output = Vec::new();
+        assert!(!program.run_with_input(7, &mut output).unwrap());
+        assert!(!program.run_with_input(8, &mut output).unwrap());
+        assert_eq!(output, [7, 8]);
+    }
+
+    #[test]
+    fn step() {
+        let mut program = Program::new(vec![3, 9, 1001, 9, 1, 9, 4, 9, 99, 0]);
+        assert_eq!(program.step().unwrap(), StepResult::NeedsInput);
+        assert_eq!(program.step().unwrap(), StepResult::NeedsInput);
+        program.push_input(41);
+        assert_eq!(program.step().unwrap(), StepResult::Continue);
+        assert_eq!(program.run_until_event().unwrap(), StepResult::Output(42));
+        assert_eq!(program.step().unwrap(), StepResult::Halted);
+        assert_eq!(program.step().unwrap(), StepResult::Halted);
+        assert_eq!(program.memory()[9], 42);
+    }
+
+    #[test]
+    fn huge_addresses() {
+        // store an input at 10^12 and output it again
+        let memory = vec![109, 1_000_000_000_000, 203, 0, 204, 0, 99];
+        let mut program = Program::new(memory.clone());
+        program.enable_history();
+        let mut output = Vec::new();
+        program.run(Some(5), &mut output).unwrap();
+        assert_eq!(output, [5]);
+        assert_eq!(program.memory().len(), 1_000_000_000_001);
+        assert_eq!(program.memory().dense_len(), memory.len());
+        assert!(program.run_back_to(0));
+        assert_eq!(program.memory(), &memory[..]);
+
+        let mut program =
+            Program::with_memory_kind(vec![1101, 2, 3, 100_000, 99], MemoryKind::Dense);
+        program.run(None, Vec::new()).unwrap();
+        assert_eq!(program.memory().dense_len(), 100_001);
+        assert_eq!(program.memory()[100_000], 5);
+    }
+
+    #[test]
+    fn outputs() {
+        // output each input doubled until an input of zero
+        let memory = vec![3, 15, 1006, 15, 14, 1002, 15, 2, 15, 4, 15, 1105, 1, 0, 99];
+        let mut program = Program::new(memory.clone());
+        let outputs: Vec<_> = program.outputs([1, 2, 3, 0]).map(Result::unwrap).collect();
+        assert_eq!(outputs, [2, 4, 6]);
+
+        let mut first = Program::new(memory.clone());
+        let mut second = Program::new(memory.clone());
+        let mut inputs = [1, 5, 0].into_iter();
+        let outputs: Vec<_> = second
+            .outputs(
+                first
+                    .outputs_with(|| inputs.next())
+                    .map(Result::unwrap)
+                    .chain([0]),
+            )
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(outputs, [4, 20]);
+
+        let mut program = Program::new(memory);
+        let mut outputs = program.outputs([7]);
+        assert_eq!(outputs.next().unwrap().unwrap(), 14);
+        assert!(matches!(
+            outputs.next(),
+            Some(Err(IntcodeError::MissingInput { ip: 0 }))
+        ));
+        assert!(outputs.next().is_none());
     }
 }
@@ -0,0 +1,392 @@
+use std::{collections::BTreeMap, io, ops::Index, sync::Arc};
+
+use crate::{format_values, invalid_data, parse_values, Word};
+
+/// How far past the end of dense memory a write may be before it is stored
+/// sparsely instead of growing dense memory.
+const DENSE_GROWTH_LIMIT: usize = 1 << 16;
+
+const PAGE_BITS: u32 = 8;
+const PAGE_SIZE: usize = 1 << PAGE_BITS;
+
+type Page<W> = [W; PAGE_SIZE];
+
+fn zero_page<W: Word>() -> Page<W> {
+    std::array::from_fn(|_| W::default())
+}
+
+/// How a [`Memory`] stores addresses past the loaded image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MemoryKind {
+    /// Grow contiguous memory to fit every write.
+    Dense,
+    /// Grow contiguously for writes near the end, but keep far writes in a
+    /// sparse map so huge addresses don't allocate everything below them.
+    #[default]
+    Sparse,
+}
+
+/// Program memory. Reading an address that was never written gives zero.
+///
+/// Contiguous memory is split into pages shared between clones, so cloning
+/// is cheap and a write only copies the page it lands in, if that page is
+/// shared.
+#[derive(Debug, Clone)]
+pub struct Memory<W = i64> {
+    kind: MemoryKind,
+    /// The number of contiguous words. Words past this in the last page are
+    /// zero.
+    dense_len: usize,
+    pages: Arc<Vec<Arc<Page<W>>>>,
+    sparse: Arc<BTreeMap<usize, W>>,
+}
+
+impl<W: Word> Memory<W> {
+    #[must_use]
+    pub fn new(image: Vec<W>, kind: MemoryKind) -> Memory<W> {
+        let pages = image
+            .chunks(PAGE_SIZE)
+            .map(|chunk| {
+                let mut page = zero_page();
+                page[..chunk.len()].clone_from_slice(chunk);
+                Arc::new(page)
+            })
+            .collect();
+        Memory {
+            kind,
+            dense_len: image.len(),
+            pages: Arc::new(pages),
+            sparse: Arc::new(BTreeMap::new()),
+        }
+    }
+
+    #[must_use]
+    pub fn kind(&self) -> MemoryKind {
+        self.kind
+    }
+
+    #[must_use]
+    pub fn get(&self, addr: usize) -> W {
+        if addr < self.dense_len {
+            self.pages[addr >> PAGE_BITS][addr % PAGE_SIZE].clone()
+        } else {
+            self.sparse.get(&addr).cloned().unwrap_or_default()
+        }
+    }
+
+    pub fn set(&mut self, addr: usize, value: W) {
+        if addr >= self.dense_len {
+            let near = addr - self.dense_len < DENSE_GROWTH_LIMIT;
+            if self.kind == MemoryKind::Sparse && !near {
+                Arc::make_mut(&mut self.sparse).insert(addr, value);
+                return;
+            }
+            self.grow(addr + 1);
+        }
+        self.set_dense(addr, value);
+    }
+
+    fn set_dense(&mut self, addr: usize, value: W) {
+        let page = &mut Arc::make_mut(&mut self.pages)[addr >> PAGE_BITS];
+        Arc::make_mut(page)[addr % PAGE_SIZE] = value;
+    }
+
+    /// Grow contiguous memory to `len`, moving in any sparse values below it.
+    fn grow(&mut self, len: usize) {
+        let page_count = len.div_ceil(PAGE_SIZE);
+        let pages = Arc::make_mut(&mut self.pages);
+        if pages.len() < page_count {
+            // new pages all share one zero page until they are written
+            let zero = Arc::new(zero_page());
+            pages.resize(page_count, zero);
+        }
+        self.dense_len = len;
+        if self
+            .sparse
+            .first_key_value()
+            .is_some_and(|(&addr, _)| addr < len)
+        {
+            let sparse = Arc::make_mut(&mut self.sparse);
+            let rest = sparse.split_off(&len);
+            for (addr, value) in std::mem::replace(sparse, rest) {
+                self.set_dense(addr, value);
+            }
+        }
+    }
+
+    /// One more than the highest address loaded or written, or `usize::MAX`
+    /// if that address is `usize::MAX`.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        match self.sparse.last_key_value() {
+            Some((&addr, _)) => addr.saturating_add(1),
+            None => self.dense_len,
+        }
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Forget every address at or above `len`.
+    pub fn truncate(&mut self, len: usize) {
+        if self
+            .sparse
+            .last_key_value()
+            .is_some_and(|(&addr, _)| addr >= len)
+        {
+            Arc::make_mut(&mut self.sparse).split_off(&len);
+        }
+        if len >= self.dense_len {
+            return;
+        }
+        for addr in len..self.dense_len.min(len.next_multiple_of(PAGE_SIZE)) {
+            self.set_dense(addr, W::default());
+        }
+        Arc::make_mut(&mut self.pages).truncate(len.div_ceil(PAGE_SIZE));
+        self.dense_len = len;
+    }
+
+    /// The number of contiguously stored words, starting at address 0.
+    #[must_use]
+    pub fn dense_len(&self) -> usize {
+        self.dense_len
+    }
+
+    fn dense(&self) -> impl Iterator<Item = &W> {
+        self.pages
+            .iter()
+            .flat_map(|page| page.iter())
+            .take(self.dense_len)
+    }
+
+    /// The sparsely stored values past the contiguous part, in address order.
+    pub fn sparse(&self) -> impl Iterator<Item = (usize, W)> + '_ {
+        self.sparse
+            .iter()
+            .map(|(&addr, value)| (addr, value.clone()))
+    }
+
+    /// Every value from address 0 up to [`Memory::len`]. This allocates the
+    /// whole range, so avoid it for memory with far sparse writes.
+    #[must_use]
+    pub fn to_vec(&self) -> Vec<W> {
+        let mut values: Vec<_> = self.dense().cloned().collect();
+        for (addr, value) in self.sparse() {
+            values.resize(addr, W::default());
+            values.push(value);
+        }
+        values
+    }
+}
+
+/// Text formats shared by traces and snapshots: the dense part as comma
+/// separated values, the sparse part as comma separated `addr:value` pairs.
+impl<W: Word> Memory<W> {
+    pub(crate) fn format_dense(&self) -> String {
+        format_values(self.dense())
+    }
+
+    pub(crate) fn format_sparse(&self) -> String {
+        let pairs: Vec<_> = self
+            .sparse()
+            .map(|(addr, value)| format!("{addr}:{value}"))
+            .collect();
+        pairs.join(",")
+    }
+
+    pub(crate) fn parse(dense: &str, sparse: &str, kind: MemoryKind) -> io::Result<Memory<W>> {
+        let mut memory = Memory::new(parse_values(dense)?, kind);
+        for pair in sparse.split(',').filter(|pair| !pair.is_empty()) {
+            let (addr, value) = pair
+                .split_once(':')
+                .and_then(|(addr, value)| Some((addr.parse().ok()?, value.parse().ok()?)))
+                .ok_or_else(|| invalid_data(format!("invalid sparse value '{pair}'")))?;
+            memory.set(addr, value);
+        }
+        Ok(memory)
+    }
+}
+
+impl<W: Word> From<Vec<W>> for Memory<W> {
+    fn from(image: Vec<W>) -> Memory<W> {
+        Memory::new(image, MemoryKind::default())
+    }
+}
+
+impl Index<usize> for Memory {
+    type Output = i64;
+
+    fn index(&self, addr: usize) -> &i64 {
+        if addr < self.dense_len {
+            &self.pages[addr >> PAGE_BITS][addr % PAGE_SIZE]
+        } else {
+            self.sparse.get(&addr).unwrap_or(&0)
+        }
+    }
+}
+
+/// Memories are equal if they have the same length and values, however they
+/// are stored.
+impl<W: Word> PartialEq for Memory<W> {
+    fn eq(&self, other: &Memory<W>) -> bool {
+        if self.len() != other.len() {
+            return false;
+        }
+        let dense_equal = if self.dense_len == other.dense_len {
+            // words past the end of the last page are always zero, and
+            // pages still shared with a clone can be skipped
+            self.pages
+                .iter()
+                .zip(other.pages.iter())
+                .all(|(a, b)| Arc::ptr_eq(a, b) || a == b)
+        } else {
+            let dense_len = self.dense_len.max(other.dense_len);
+            (0..dense_len).all(|addr| self.get(addr) == other.get(addr))
+        };
+        // sparse values are compared by address, as a zero may be stored in
+        // one and missing from the other
+        dense_equal
+            && (Arc::ptr_eq(&self.sparse, &other.sparse)
+                || self
+                    .sparse
+                    .keys()
+                    .chain(other.sparse.keys())
+                    .all(|&addr| self.get(addr) == other.get(addr)))
+    }
+}
+
+impl<W: Word> Eq for Memory<W> {}
+
+impl<W: Word> PartialEq<[W]> for Memory<W> {
+    fn eq(&self, other: &[W]) -> bool {
+        self.len() == other.len()
+            && other
+                .iter()
+                .enumerate()
+                .all(|(addr, value)| self.get(addr) == *value)
+    }
+}
+
+impl<W: Word, const N: usize> PartialEq<[W; N]> for Memory<W> {
+    fn eq(&self, other: &[W; N]) -> bool {
+        *self == other[..]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sparse_writes() {
+        let mut memory = Memory::<i64>::from(vec![1, 2, 3]);
+        memory.set(10, 4);
+        memory.set(1_000_000_000_000, 5);
+        assert_eq!(memory.dense_len(), 11);
+        assert_eq!(
+            memory.sparse().collect::<Vec<_>>(),
+            [(1_000_000_000_000, 5)]
+        );
+        assert_eq!(memory.len(), 1_000_000_000_001);
+        assert_eq!(memory.get(1_000_000_000_000), 5);
+        assert_eq!(memory[999_999_999_999], 0);
+        memory.set(usize::MAX, 6);
+        assert_eq!(memory.len(), usize::MAX);
+        assert_eq!(memory.get(usize::MAX), 6);
+
+        let mut dense = Memory::<i64>::new(vec![1, 2, 3], MemoryKind::Dense);
+        dense.set(100_000, 4);
+        assert_eq!(dense.dense_len(), 100_001);
+        assert_eq!(dense.sparse().count(), 0);
+    }
+
+    #[test]
+    fn grow_into_sparse() {
+        let mut memory = Memory::<i64>::from(vec![]);
+        memory.set(DENSE_GROWTH_LIMIT + 5, 1);
+        memory.set(DENSE_GROWTH_LIMIT + 10, 2);
+        assert_eq!(memory.sparse().count(), 2);
+        memory.set(DENSE_GROWTH_LIMIT - 1, 3);
+        memory.set(DENSE_GROWTH_LIMIT + 7, 4);
+        assert_eq!(memory.dense_len(), DENSE_GROWTH_LIMIT + 8);
+        assert_eq!(
+            memory.sparse().collect::<Vec<_>>(),
+            [(DENSE_GROWTH_LIMIT + 10, 2)]
+        );
+        assert_eq!(memory.get(DENSE_GROWTH_LIMIT + 5), 1);
+        assert_eq!(memory.get(DENSE_GROWTH_LIMIT + 10), 2);
+        assert_eq!(memory.len(), DENSE_GROWTH_LIMIT + 11);
+    }
+
+    #[test]
+    fn equality() {
+        let mut sparse = Memory::<i64>::from(vec![1]);
+        sparse.set(DENSE_GROWTH_LIMIT + 1, 7);
+        let mut dense = Memory::<i64>::new(vec![1], MemoryKind::Dense);
+        dense.set(DENSE_GROWTH_LIMIT + 1, 7);
+        assert_eq!(sparse, dense);
+        assert_eq!(sparse.to_vec(), dense.to_vec());
+        dense.set(5, 1);
+        assert_ne!(sparse, dense);
+
+        sparse.truncate(1);
+        assert_eq!(sparse, [1]);
+
+        // a zero written far away reads the same as one never written
+        let mut zero = Memory::<i64>::from(vec![1]);
+        zero.set(1_000_000, 0);
+        zero.set(2_000_000, 5);
+        let mut other = Memory::<i64>::from(vec![1]);
+        other.set(2_000_000, 5);
+        assert_eq!(zero, other);
+        other.set(1_000_000, 3);
+        assert_ne!(zero, other);
+    }
+
+    #[test]
+    fn copy_on_write() {
+        let original = Memory::from((0..1000).collect::<Vec<_>>());
+        let mut copy = original.clone();
+        assert!(Arc::ptr_eq(&original.pages, &copy.pages));
+        copy.set(300, -1);
+        let shared = (0..original.pages.len())
+            .filter(|&i| Arc::ptr_eq(&original.pages[i], &copy.pages[i]))
+            .count();
+        assert_eq!(shared, original.pages.len() - 1);
+        assert_eq!(original[300], 300);
+        assert_eq!(copy[300], -1);
+
+        copy.truncate(PAGE_SIZE + 10);
+        copy.set(PAGE_SIZE + 20, 5);
+        assert_eq!(copy.get(PAGE_SIZE + 11), 0);
+        assert_eq!(copy.len(), PAGE_SIZE + 21);
+        assert_eq!(original.get(PAGE_SIZE + 11), PAGE_SIZE as i64 + 11);
+    }
+
+    #[test]
+    fn text_round_trip() {
+        let mut memory = Memory::<i64>::from(vec![1, -2]);
+        memory.set(1 << 40, -3);
+        memory.set(1 << 50, 4);
+        assert_eq!(memory.format_dense(), "1,-2");
+        assert_eq!(
+            memory.format_sparse(),
+            "1099511627776:-3,1125899906842624:4"
+        );
+        let parsed = Memory::<i64>::parse(
+            &memory.format_dense(),
+            &memory.format_sparse(),
+            memory.kind(),
+        )
+        .unwrap();
+        assert_eq!(parsed, memory);
+        assert_eq!(
+            Memory::<i64>::parse("1", "5", MemoryKind::Sparse)
+                .unwrap_err()
+                .to_string(),
+            "invalid sparse value '5'"
+        );
+    }
+}
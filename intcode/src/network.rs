@@ -0,0 +1,407 @@
+//! A simulated network of Intcode machines, as in day 23 of Advent of Code
+//! 2019.
+//!
+//! Each machine reads its address when it boots. After that it outputs
+//! packets as three values, the destination address then `x` and `y`, and
+//! reads packets sent to it as `x` then `y`. A machine with no packets
+//! waiting reads -1. A [`Monitor`] sees every packet and can add its own, for
+//! example to wake the network up when it goes idle.
+//!
+//! Machines run in turn in address order, each until it needs input, so runs
+//! are reproducible.
+
+use std::{collections::VecDeque, error::Error, fmt};
+
+use crate::{IntcodeError, Program, StepResult, Word};
+
+/// A packet sent by a machine or a monitor.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Packet<W = i64> {
+    /// The address of the machine that sent it, or `None` for packets from a
+    /// monitor.
+    pub source: Option<usize>,
+    pub destination: W,
+    pub x: W,
+    pub y: W,
+}
+
+/// What a [`Monitor`] wants the network to do next.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Action<W = i64> {
+    Continue,
+    /// Deliver a packet, then continue.
+    Send(Packet<W>),
+    /// Stop the network. A packet being seen is still delivered first, so
+    /// the network can be run again from where it stopped.
+    Stop,
+}
+
+/// Observes and adds to the traffic on a [`Network`].
+pub trait Monitor<W = i64> {
+    /// Called with every packet a machine sends, before it is delivered.
+    /// Packets to addresses without a machine are only seen here.
+    fn sent(&mut self, _packet: &Packet<W>) -> Action<W> {
+        Action::Continue
+    }
+
+    /// Called when the network is idle: no packets are waiting and every
+    /// machine has just read -1 without sending anything. If this returns
+    /// [`Action::Continue`] it is called again after the next round.
+    fn idle(&mut self) -> Action<W> {
+        Action::Stop
+    }
+}
+
+/// No monitor: the network stops when it goes idle.
+impl<W> Monitor<W> for () {}
+
+/// Watches packets sent to its address, and when the network goes idle sends
+/// the last one on to address 0. It stops the network rather than send the
+/// same `y` twice in a row.
+#[derive(Debug, Clone)]
+pub struct Nat<W = i64> {
+    address: W,
+    first: Option<Packet<W>>,
+    last: Option<Packet<W>>,
+    wakes: Vec<W>,
+}
+
+impl<W: Word> Nat<W> {
+    #[must_use]
+    pub fn new(address: W) -> Nat<W> {
+        Nat {
+            address,
+            first: None,
+            last: None,
+            wakes: Vec::new(),
+        }
+    }
+
+    /// The first packet sent to the NAT.
+    #[must_use]
+    pub fn first(&self) -> Option<&Packet<W>> {
+        self.first.as_ref()
+    }
+
+    /// The `y` value of each packet the NAT has sent to address 0.
+    #[must_use]
+    pub fn wakes(&self) -> &[W] {
+        &self.wakes
+    }
+}
+
+impl<W: Word> Monitor<W> for Nat<W> {
+    fn sent(&mut self, packet: &Packet<W>) -> Action<W> {
+        if packet.destination == self.address {
+            self.first.get_or_insert_with(|| packet.clone());
+            self.last = Some(packet.clone());
+        }
+        Action::Continue
+    }
+
+    fn idle(&mut self) -> Action<W> {
+        let Some(last) = &self.last else {
+            return Action::Stop;
+        };
+        if self.wakes.last() == Some(&last.y) {
+            return Action::Stop;
+        }
+        self.wakes.push(last.y.clone());
+        Action::Send(Packet {
+            source: None,
+            destination: W::default(),
+            x: last.x.clone(),
+            y: last.y.clone(),
+        })
+    }
+}
+
+/// Packet counts for one machine.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct NodeStats {
+    pub sent: u64,
+    pub received: u64,
+    /// How many times the machine read -1.
+    pub idle_reads: u64,
+}
+
+/// How a [`Network::run`] call ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NetworkOutcome {
+    /// Every machine halted.
+    Halted,
+    /// The monitor stopped the network.
+    Stopped,
+}
+
+/// A machine on a [`Network`] failed.
+#[derive(Debug)]
+pub struct NetworkError<W = i64> {
+    pub address: usize,
+    pub error: IntcodeError<W>,
+}
+
+impl<W: fmt::Display> fmt::Display for NetworkError<W> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "machine {} failed: {}", self.address, self.error)
+    }
+}
+
+impl<W: fmt::Debug + fmt::Display + 'static> Error for NetworkError<W> {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+struct Node<W> {
+    program: Program<W>,
+    queue: VecDeque<(W, W)>,
+    /// Values of a packet the machine is part way through sending.
+    output: Vec<W>,
+    halted: bool,
+    stats: NodeStats,
+}
+
+/// A network of machines with addresses counting up from 0.
+pub struct Network<W = i64> {
+    nodes: Vec<Node<W>>,
+}
+
+impl<W: Word> Network<W> {
+    /// Boot `size` copies of `program`, giving each its address.
+    #[must_use]
+    pub fn new(program: &Program<W>, size: usize) -> Network<W> {
+        let nodes = (0..size)
+            .map(|address| {
+                let mut program = program.clone();
+                program.push_input(W::from_i64(address as i64));
+                Node {
+                    program,
+                    queue: VecDeque::new(),
+                    output: Vec::new(),
+                    halted: false,
+                    stats: NodeStats::default(),
+                }
+            })
+            .collect();
+        Network { nodes }
+    }
+
+    #[must_use]
+    pub fn program(&self, address: usize) -> &Program<W> {
+        &self.nodes[address].program
+    }
+
+    #[must_use]
+    pub fn stats(&self, address: usize) -> NodeStats {
+        self.nodes[address].stats
+    }
+
+    /// Queue a packet for its destination. Packets to addresses without a
+    /// machine are dropped.
+    pub fn send(&mut self, packet: Packet<W>) {
+        let node = packet
+            .destination
+            .to_usize()
+            .and_then(|address| self.nodes.get_mut(address));
+        if let Some(node) = node {
+            node.queue.push_back((packet.x, packet.y));
+        }
+    }
+
+    /// Run until every machine halts or `monitor` stops the network. Without
+    /// a monitor, pass `&mut ()` to run until the network goes idle.
+    pub fn run<M: Monitor<W>>(
+        &mut self,
+        monitor: &mut M,
+    ) -> Result<NetworkOutcome, NetworkError<W>> {
+        loop {
+            let mut active = false;
+            for address in 0..self.nodes.len() {
+                if self.nodes[address].halted {
+                    continue;
+                }
+                while let Some(packet) = self.run_node(address)? {
+                    active = true;
+                    self.nodes[address].stats.sent += 1;
+                    let action = monitor.sent(&packet);
+                    if let Action::Send(extra) = &action {
+                        self.send(extra.clone());
+                    }
+                    self.send(packet);
+                    if action == Action::Stop {
+                        return Ok(NetworkOutcome::Stopped);
+                    }
+                }
+
+                let node = &mut self.nodes[address];
+                if node.halted {
+                    continue;
+                }
+                if let Some((x, y)) = node.queue.pop_front() {
+                    node.program.push_input(x);
+                    node.program.push_input(y);
+                    node.stats.received += 1;
+                    active = true;
+                } else {
+                    node.program.push_input(W::from_i64(-1));
+                    node.stats.idle_reads += 1;
+                }
+            }
+
+            if self.nodes.iter().all(|node| node.halted) {
+                return Ok(NetworkOutcome::Halted);
+            }
+            if !active {
+                match monitor.idle() {
+                    Action::Continue => {}
+                    Action::Send(packet) => self.send(packet),
+                    Action::Stop => return Ok(NetworkOutcome::Stopped),
+                }
+            }
+        }
+    }
+
+    /// Run a machine until it sends a packet, needs input or halts.
+    fn run_node(&mut self, address: usize) -> Result<Option<Packet<W>>, NetworkError<W>> {
+        let node = &mut self.nodes[address];
+        loop {
+            let result = node
+                .program
+                .run_until_event()
+                .map_err(|error| NetworkError { address, error })?;
+            match result {
+                StepResult::Output(value) => {
+                    node.output.push(value);
+                    if let [destination, x, y] = &mut node.output[..] {
+                        let packet = Packet {
+                            source: Some(address),
+                            destination: std::mem::take(destination),
+                            x: std::mem::take(x),
+                            y: std::mem::take(y),
+                        };
+                        node.output.clear();
+                        return Ok(Some(packet));
+                    }
+                }
+                StepResult::NeedsInput => return Ok(None),
+                StepResult::Halted => {
+                    node.halted = true;
+                    return Ok(None);
+                }
+                StepResult::Continue => unreachable!(),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm;
+
+    /// Machines pass packets along in address order, adding one to `x`, and
+    /// the last one sends them to address 255. Machine 0 starts things off.
+    fn ring() -> Program {
+        Program::new(
+            asm::assemble(
+                "
+                        IN [addr]
+                        JT [addr], #loop
+                        OUT #1
+                        OUT #100
+                        OUT #7
+                loop:   IN [x]
+                        EQ [x], #-1, [tmp]
+                        JT [tmp], #loop
+                        IN [y]
+                        ADD [addr], #1, [dest]
+                        EQ [dest], #3, [tmp]
+                        JF [tmp], #send
+                        ADD #255, #0, [dest]
+                send:   OUT [dest]
+                        ADD [x], #1, [x]
+                        OUT [x]
+                        OUT [y]
+                        JT #1, #loop
+                addr:   .data 0
+                x:      .data 0
+                y:      .data 0
+                dest:   .data 0
+                tmp:    .data 0
+                ",
+            )
+            .unwrap(),
+        )
+    }
+
+    #[test]
+    fn idle() {
+        let mut network = Network::new(&ring(), 3);
+        assert_eq!(network.run(&mut ()).unwrap(), NetworkOutcome::Stopped);
+        for address in 0..3 {
+            assert_eq!(network.stats(address).sent, 1);
+        }
+        assert_eq!(network.stats(0).received, 0);
+        assert_eq!(network.stats(2).received, 1);
+    }
+
+    #[test]
+    fn nat() {
+        let mut network = Network::new(&ring(), 3);
+        let mut nat = Nat::new(255);
+        assert_eq!(network.run(&mut nat).unwrap(), NetworkOutcome::Stopped);
+        assert_eq!(
+            nat.first(),
+            Some(&Packet {
+                source: Some(2),
+                destination: 255,
+                x: 102,
+                y: 7
+            })
+        );
+        // the second packet to the NAT has the same y, so it isn't sent on
+        assert_eq!(nat.wakes(), [7]);
+        assert_eq!(network.stats(0).received, 1);
+        for address in 0..3 {
+            assert_eq!(network.stats(address).sent, 2);
+            assert!(network.stats(address).idle_reads > 0);
+        }
+    }
+
+    #[test]
+    fn halted() {
+        // machines that halt after reading their address, and one that fails
+        let mut network = Network::new(&Program::new(vec![3, 0, 99]), 4);
+        assert_eq!(network.run(&mut ()).unwrap(), NetworkOutcome::Halted);
+
+        let mut network = Network::new(&Program::new(vec![3, 7, 1005, 7, 6, 99, 98, 0]), 3);
+        let err = network.run(&mut ()).unwrap_err();
+        assert_eq!(err.address, 1);
+        assert!(err.to_string().starts_with("machine 1 failed: "));
+    }
+
+    #[test]
+    fn stop_on_packet() {
+        struct StopFirst;
+        impl Monitor for StopFirst {
+            fn sent(&mut self, _packet: &Packet) -> Action {
+                Action::Stop
+            }
+        }
+
+        // the packet that stopped the network still arrives when it resumes
+        let mut network = Network::new(&ring(), 3);
+        assert_eq!(
+            network.run(&mut StopFirst).unwrap(),
+            NetworkOutcome::Stopped
+        );
+        assert_eq!(network.stats(0).sent, 1);
+        assert_eq!(network.stats(1).received, 0);
+        assert_eq!(network.run(&mut ()).unwrap(), NetworkOutcome::Stopped);
+        for address in 0..3 {
+            assert_eq!(network.stats(address).sent, 1);
+        }
+        assert_eq!(network.stats(1).received, 1);
+    }
+}
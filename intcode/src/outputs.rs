@@ -0,0 +1,56 @@
+use crate::{IntcodeError, IntcodeInput, Program, StepResult, Word};
+
+/// An iterator that runs a [`Program`] lazily, yielding each output value.
+///
+/// Created by [`Program::outputs`] and [`Program::outputs_with`]. When the
+/// program blocks on input, the next value is pulled from the input source.
+/// The iterator ends when the program halts, or after yielding an error.
+pub struct Outputs<'a, I, W = i64> {
+    program: &'a mut Program<W>,
+    input: I,
+    done: bool,
+}
+
+impl<'a, I: IntcodeInput<W>, W: Word> Outputs<'a, I, W> {
+    pub(crate) fn new(program: &'a mut Program<W>, input: I) -> Outputs<'a, I, W> {
+        Outputs {
+            program,
+            input,
+            done: false,
+        }
+    }
+
+    fn next_output(&mut self) -> Result<Option<W>, IntcodeError<W>> {
+        loop {
+            match self.program.run_until_event()? {
+                StepResult::NeedsInput => {
+                    let ip = self.program.ip();
+                    let value = self
+                        .input
+                        .read_input()
+                        .map_err(|source| IntcodeError::Input { ip, source })?
+                        .ok_or(IntcodeError::MissingInput { ip })?;
+                    self.program.push_input(value);
+                }
+                StepResult::Output(value) => return Ok(Some(value)),
+                StepResult::Halted => return Ok(None),
+                StepResult::Continue => unreachable!(),
+            }
+        }
+    }
+}
+
+impl<I: IntcodeInput<W>, W: Word> Iterator for Outputs<'_, I, W> {
+    type Item = Result<W, IntcodeError<W>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let result = self.next_output().transpose();
+        if !matches!(result, Some(Ok(_))) {
+            self.done = true;
+        }
+        result
+    }
+}
@@ -0,0 +1,133 @@
+use crate::{Program, Word};
+
+/// What arithmetic does when a result doesn't fit in a [`Word`].
+///
+/// This covers additions, multiplications, relative base adjustments and
+/// relative addresses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Overflow {
+    /// Wrap around in two's complement.
+    Wrapping,
+    /// Fail with [`IntcodeError::Overflow`](crate::IntcodeError::Overflow).
+    #[default]
+    Checked,
+    /// Clamp to the smallest or largest word, like `i64::MIN` or `i64::MAX`.
+    Saturating,
+}
+
+impl Overflow {
+    /// Add under this policy, or `None` if a checked addition overflows.
+    #[must_use]
+    pub fn add<W: Word>(self, x: W, y: W) -> Option<W> {
+        match self {
+            Overflow::Wrapping => Some(x.wrapping_add(y)),
+            Overflow::Checked => x.checked_add(y),
+            Overflow::Saturating => Some(x.saturating_add(y)),
+        }
+    }
+
+    /// Multiply under this policy, or `None` if a checked multiplication
+    /// overflows.
+    #[must_use]
+    pub fn mul<W: Word>(self, x: W, y: W) -> Option<W> {
+        match self {
+            Overflow::Wrapping => Some(x.wrapping_mul(y)),
+            Overflow::Checked => x.checked_mul(y),
+            Overflow::Saturating => Some(x.saturating_mul(y)),
+        }
+    }
+
+    /// A Rust function `name(ip, a, b) -> Result<i64, String>` applying
+    /// `op` (`add` or `mul`) under this policy, for generated code.
+    pub(crate) fn rust_fn(self, name: &str, op: &str) -> String {
+        let (ip, body) = match self {
+            Overflow::Wrapping => ("_ip", format!("Ok(a.wrapping_{op}(b))")),
+            Overflow::Checked => (
+                "ip",
+                format!("a.checked_{op}(b).ok_or_else(|| format!(\"overflow at {{ip}}\"))"),
+            ),
+            Overflow::Saturating => ("_ip", format!("Ok(a.saturating_{op}(b))")),
+        };
+        format!("fn {name}({ip}: usize, a: i64, b: i64) -> Result<i64, String> {{\n    {body}\n}}")
+    }
+}
+
+impl<W: Word> Program<W> {
+    /// Choose what happens when arithmetic overflows. Programs start with
+    /// [`Overflow::Checked`].
+    pub fn set_overflow(&mut self, overflow: Overflow) {
+        self.overflow = overflow;
+    }
+
+    #[must_use]
+    pub fn overflow(&self) -> Overflow {
+        self.overflow
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::IntcodeError;
+
+    /// Run with each policy, with and without the decode cache.
+    fn outputs(memory: &[i64], overflow: Overflow) -> Result<Vec<i64>, String> {
+        let mut results = [false, true].map(|cached| {
+            let mut program = Program::new(memory.to_vec());
+            program.set_overflow(overflow);
+            if cached {
+                program.enable_decode_cache();
+            }
+            let mut output = Vec::new();
+            program
+                .run(None, &mut output)
+                .map(|()| output)
+                .map_err(|err| err.to_string())
+        });
+        assert_eq!(results[0], results[1]);
+        std::mem::replace(&mut results[0], Ok(Vec::new()))
+    }
+
+    #[test]
+    fn day09_big_numbers() {
+        let square = [1102, 34915192, 34915192, 7, 4, 7, 99, 0];
+        let large = [104, 1125899906842624, 99];
+        for overflow in [Overflow::Wrapping, Overflow::Checked, Overflow::Saturating] {
+            assert_eq!(outputs(&square, overflow), Ok(vec![1219070632396864]));
+            assert_eq!(outputs(&large, overflow), Ok(vec![1125899906842624]));
+        }
+    }
+
+    #[test]
+    fn policies() {
+        // square the large day09 number
+        let square = [1002, 7, 1125899906842624, 7, 4, 7, 99, 1125899906842624];
+        // negate the largest number and double it
+        let sum = [1002, 11, -1, 11, 1, 11, 11, 11, 4, 11, 99, i64::MAX];
+        assert_eq!(
+            outputs(&square, Overflow::Checked),
+            Err("overflow in instruction 1002 at address 0".to_string())
+        );
+        assert_eq!(outputs(&square, Overflow::Wrapping), Ok(vec![0]));
+        assert_eq!(outputs(&square, Overflow::Saturating), Ok(vec![i64::MAX]));
+
+        assert_eq!(
+            outputs(&sum, Overflow::Checked),
+            Err("overflow in instruction 1 at address 4".to_string())
+        );
+        assert_eq!(outputs(&sum, Overflow::Wrapping), Ok(vec![2]));
+        assert_eq!(outputs(&sum, Overflow::Saturating), Ok(vec![i64::MIN]));
+    }
+
+    #[test]
+    fn relative_base() {
+        let mut program = Program::new(vec![109, i64::MAX, 109, 1, 99]);
+        let err = program.run(None, Vec::new()).unwrap_err();
+        assert!(matches!(err, IntcodeError::Overflow { ip: 2, .. }));
+
+        let mut program = Program::new(vec![109, i64::MAX, 109, 1, 99]);
+        program.set_overflow(Overflow::Wrapping);
+        program.run(None, Vec::new()).unwrap();
+        assert_eq!(program.relative_base(), i64::MIN);
+    }
+}
@@ -0,0 +1,253 @@
+use std::{collections::HashMap, fmt::Write};
+
+use crate::{disasm, Instruction, IntcodeError, Memory, ParamMode, Program, StepResult, Word};
+
+/// A loop found from a backward jump that was taken: the code from the jump
+/// target up to and including the jump.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Loop {
+    pub start: usize,
+    /// The address of the jump back to `start`.
+    pub end: usize,
+    /// How many times the jump back was taken.
+    pub iterations: u64,
+    /// Instructions executed at any address from `start` to `end`. This is
+    /// an upper bound on the cost of the loop: it also counts passes through
+    /// the range that weren't part of the loop, such as code shared with
+    /// another loop, so the ranges of nested loops count the same
+    /// instructions.
+    pub executed_in_range: u64,
+}
+
+/// Execution counts collected while profiling.
+#[derive(Debug, Clone, Default)]
+pub struct Profile {
+    total: u64,
+    by_addr: HashMap<usize, u64>,
+    by_instruction: HashMap<Instruction, u64>,
+    /// Taken backward jumps by jump address and target.
+    back_jumps: HashMap<(usize, usize), u64>,
+}
+
+fn sorted_by_count<K: Ord + Copy>(counts: &HashMap<K, u64>) -> Vec<(K, u64)> {
+    let mut counts: Vec<_> = counts.iter().map(|(&key, &count)| (key, count)).collect();
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    counts
+}
+
+fn format_modes(instruction: Instruction) -> String {
+    let modes: Vec<_> = instruction
+        .param_modes()
+        .iter()
+        .map(|mode| match mode {
+            ParamMode::Position => "pos",
+            ParamMode::Immediate => "imm",
+            ParamMode::Relative => "rel",
+        })
+        .collect();
+    format!("{} {}", instruction.opcode.mnemonic(), modes.join(","))
+        .trim_end()
+        .to_string()
+}
+
+impl Profile {
+    fn record(&mut self, ip: usize, word: Option<i64>, next_ip: usize) {
+        self.total += 1;
+        *self.by_addr.entry(ip).or_default() += 1;
+        if let Some(Ok(instruction)) = word.map(Instruction::decode) {
+            *self.by_instruction.entry(instruction).or_default() += 1;
+        }
+        if next_ip <= ip {
+            *self.back_jumps.entry((ip, next_ip)).or_default() += 1;
+        }
+    }
+
+    /// The number of instructions executed.
+    #[must_use]
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+
+    /// How many times the instruction at `addr` was executed.
+    #[must_use]
+    pub fn count(&self, addr: usize) -> u64 {
+        self.by_addr.get(&addr).copied().unwrap_or(0)
+    }
+
+    /// Execution counts by address, most executed first.
+    #[must_use]
+    pub fn hottest(&self) -> Vec<(usize, u64)> {
+        sorted_by_count(&self.by_addr)
+    }
+
+    /// Execution counts by opcode and parameter modes, most executed first.
+    #[must_use]
+    pub fn by_instruction(&self) -> Vec<(Instruction, u64)> {
+        let mut counts: Vec<_> = self
+            .by_instruction
+            .iter()
+            .map(|(&instruction, &count)| (instruction, count))
+            .collect();
+        counts.sort_by_key(|&(instruction, count)| (u64::MAX - count, instruction.encode()));
+        counts
+    }
+
+    /// Loops that ran, those with the most instructions executed in their
+    /// range first.
+    #[must_use]
+    pub fn loops(&self) -> Vec<Loop> {
+        let mut loops: Vec<_> = self
+            .back_jumps
+            .iter()
+            .map(|(&(end, start), &iterations)| Loop {
+                start,
+                end,
+                iterations,
+                executed_in_range: (start..=end).map(|addr| self.count(addr)).sum(),
+            })
+            .collect();
+        loops.sort_by(|a, b| {
+            b.executed_in_range
+                .cmp(&a.executed_in_range)
+                .then(a.start.cmp(&b.start))
+        });
+        loops
+    }
+
+    /// A text report of the `limit` most costly instructions, opcode and mode
+    /// combinations and loops, disassembling instructions from `memory`.
+    #[must_use]
+    pub fn report<W: Word>(&self, memory: &Memory<W>, limit: usize) -> String {
+        let percent = |count: u64| 100.0 * count as f64 / self.total.max(1) as f64;
+        let mut text = format!("{} instructions executed\n", self.total);
+
+        text.push_str("\nhottest instructions\n");
+        for (addr, count) in self.hottest().into_iter().take(limit) {
+            let line = disasm::disassemble_at(memory, addr);
+            writeln!(text, "{count:>10} {:>5.1}% {line}", percent(count)).unwrap();
+        }
+
+        text.push_str("\nby opcode and modes\n");
+        for (instruction, count) in self.by_instruction().into_iter().take(limit) {
+            let modes = format_modes(instruction);
+            writeln!(text, "{count:>10} {:>5.1}% {modes}", percent(count)).unwrap();
+        }
+
+        text.push_str("\nhot loops\n");
+        for found in self.loops().into_iter().take(limit) {
+            writeln!(
+                text,
+                "{:>10} {:>5.1}% {}..={} ({} iterations)",
+                found.executed_in_range,
+                percent(found.executed_in_range),
+                found.start,
+                found.end,
+                found.iterations
+            )
+            .unwrap();
+        }
+        text
+    }
+}
+
+impl<W: Word> Program<W> {
+    /// Start counting executed instructions, discarding any previous profile.
+    pub fn start_profile(&mut self) {
+        self.profile = Some(Box::default());
+    }
+
+    /// Stop profiling and return the collected counts.
+    pub fn take_profile(&mut self) -> Option<Profile> {
+        self.profile.take().map(|profile| *profile)
+    }
+
+    #[must_use]
+    pub fn profile(&self) -> Option<&Profile> {
+        self.profile.as_deref()
+    }
+
+    pub(crate) fn step_profiled(&mut self) -> Result<StepResult<W>, IntcodeError<W>> {
+        let ip = self.ip;
+        let word = self.read(ip).to_i64();
+        let result = self.step_unprofiled()?;
+        if let (Some(profile), StepResult::Continue | StepResult::Output(_)) =
+            (&mut self.profile, &result)
+        {
+            profile.record(ip, word, self.ip);
+        }
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm;
+
+    #[test]
+    fn counts_and_loops() {
+        let memory = asm::assemble(
+            "
+                    IN [n]
+            loop:   ADD [n], #-1, [n]
+                    OUT [n]
+                    JT [n], #loop
+                    HALT
+            n:      .data 0
+            ",
+        )
+        .unwrap();
+        let mut program = Program::new(memory);
+        program.start_profile();
+        program.run(Some(3), Vec::new()).unwrap();
+        let profile = program.take_profile().unwrap();
+        assert!(program.profile().is_none());
+
+        assert_eq!(profile.total(), 10);
+        assert_eq!(profile.count(0), 1);
+        assert_eq!(profile.count(2), 3);
+        assert_eq!(profile.count(11), 0);
+        assert_eq!(profile.hottest()[..3], [(2, 3), (6, 3), (8, 3)]);
+        let by_instruction: Vec<_> = profile
+            .by_instruction()
+            .into_iter()
+            .map(|(instruction, count)| (format_modes(instruction), count))
+            .collect();
+        assert_eq!(
+            by_instruction,
+            [
+                ("OUT pos".to_string(), 3),
+                ("ADD pos,imm,pos".to_string(), 3),
+                ("JT pos,imm".to_string(), 3),
+                ("IN pos".to_string(), 1),
+            ]
+        );
+        assert_eq!(
+            profile.loops(),
+            [Loop {
+                start: 2,
+                end: 8,
+                iterations: 2,
+                executed_in_range: 9,
+            }]
+        );
+
+        let report = profile.report(program.memory(), 2);
+        assert_eq!(
+            report,
+            "10 instructions executed
+
+hottest instructions
+         3  30.0%     2: 1001 12 -1 12            ADD [12], #-1, [12]
+         3  30.0%     6: 4 12                     OUT [12]
+
+by opcode and modes
+         3  30.0% OUT pos
+         3  30.0% ADD pos,imm,pos
+
+hot loops
+         9  90.0% 2..=8 (2 iterations)
+"
+        );
+    }
+}
@@ -0,0 +1,392 @@
+use std::{
+    error::Error,
+    fmt,
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Mutex,
+    },
+    thread,
+};
+
+use crate::{IntcodeError, Program, StepResult, Word};
+
+/// Runs a group of programs whose outputs feed each other's inputs.
+///
+/// Each program blocks on input until another program sends it a value. A
+/// run ends when every program has halted, or when every program left is
+/// waiting for input that can never arrive. Every output is also recorded so
+/// results can be read from any program afterwards.
+pub struct Scheduler<W = i64> {
+    programs: Vec<Program<W>>,
+    routes: Vec<Vec<usize>>,
+    outputs: Vec<Vec<W>>,
+    halted: Vec<bool>,
+}
+
+/// A program run by a [`Scheduler`] failed.
+#[derive(Debug)]
+pub struct SchedulerError<W = i64> {
+    pub program: usize,
+    pub error: IntcodeError<W>,
+}
+
+impl<W: fmt::Display> fmt::Display for SchedulerError<W> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "program {} failed: {}", self.program, self.error)
+    }
+}
+
+impl<W: fmt::Debug + fmt::Display + 'static> Error for SchedulerError<W> {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+impl<W: Word> Default for Scheduler<W> {
+    fn default() -> Scheduler<W> {
+        Scheduler {
+            programs: Vec::new(),
+            routes: Vec::new(),
+            outputs: Vec::new(),
+            halted: Vec::new(),
+        }
+    }
+}
+
+impl<W: Word> Scheduler<W> {
+    #[must_use]
+    pub fn new() -> Scheduler<W> {
+        Scheduler::default()
+    }
+
+    /// Add a program and return its id. Ids count up from 0.
+    pub fn add(&mut self, program: Program<W>) -> usize {
+        self.programs.push(program);
+        self.routes.push(Vec::new());
+        self.outputs.push(Vec::new());
+        self.halted.push(false);
+        self.programs.len() - 1
+    }
+
+    /// Send the outputs of program `from` to the input of program `to`. A
+    /// program connected to several others sends each of them every output,
+    /// and one connected from several others reads their outputs as they
+    /// arrive.
+    pub fn connect(&mut self, from: usize, to: usize) {
+        assert!(to < self.programs.len(), "no program {to}");
+        if !self.routes[from].contains(&to) {
+            self.routes[from].push(to);
+        }
+    }
+
+    /// Queue a value for program `id`, such as a setting it reads first.
+    pub fn push_input(&mut self, id: usize, value: W) {
+        self.programs[id].push_input(value);
+    }
+
+    #[must_use]
+    pub fn program(&self, id: usize) -> &Program<W> {
+        &self.programs[id]
+    }
+
+    pub fn program_mut(&mut self, id: usize) -> &mut Program<W> {
+        &mut self.programs[id]
+    }
+
+    /// Every value program `id` has output so far.
+    #[must_use]
+    pub fn outputs(&self, id: usize) -> &[W] {
+        &self.outputs[id]
+    }
+
+    #[must_use]
+    pub fn is_halted(&self, id: usize) -> bool {
+        self.halted[id]
+    }
+
+    /// Run the programs in turn on this thread, each until it needs input
+    /// that hasn't arrived yet or halts. The order is always the same, so
+    /// runs are reproducible.
+    ///
+    /// Returns true if every program halted, or false if the rest are all
+    /// waiting for input.
+    pub fn run(&mut self) -> Result<bool, SchedulerError<W>> {
+        let mut progress = true;
+        while progress {
+            progress = false;
+            for id in 0..self.programs.len() {
+                while !self.halted[id] {
+                    let result = self.programs[id]
+                        .run_until_event()
+                        .map_err(|error| SchedulerError { program: id, error })?;
+                    match result {
+                        StepResult::NeedsInput => break,
+                        StepResult::Output(value) => self.deliver(id, value),
+                        StepResult::Halted => self.halted[id] = true,
+                        StepResult::Continue => unreachable!(),
+                    }
+                    progress = true;
+                }
+            }
+        }
+        Ok(self.halted.iter().all(|&halted| halted))
+    }
+
+    fn deliver(&mut self, id: usize, value: W) {
+        for &to in &self.routes[id] {
+            self.programs[to].push_input(value.clone());
+        }
+        self.outputs[id].push(value);
+    }
+
+    /// Run every program on its own thread, passing values over channels.
+    ///
+    /// Returns like [`Scheduler::run`]. When several programs send to the
+    /// same one, the order their values arrive in depends on how the threads
+    /// are scheduled. If programs fail, the error from the one with the
+    /// lowest id is returned.
+    pub fn run_threaded(&mut self) -> Result<bool, SchedulerError<W>> {
+        let mut senders = Vec::new();
+        let mut receivers = Vec::new();
+        for &halted in &self.halted {
+            let (sender, receiver) = mpsc::channel();
+            senders.push((!halted).then_some(sender));
+            receivers.push(receiver);
+        }
+        let network = Mutex::new(Network {
+            alive: senders.iter().flatten().count(),
+            senders,
+            waiting: 0,
+            in_flight: 0,
+        });
+
+        let results: Vec<_> = thread::scope(|scope| {
+            let network = &network;
+            let threads: Vec<_> = self
+                .programs
+                .iter_mut()
+                .zip(receivers)
+                .enumerate()
+                .filter(|&(id, _)| !self.halted[id])
+                .map(|(id, (program, receiver))| {
+                    let route = &self.routes[id][..];
+                    let thread =
+                        scope.spawn(move || run_connected(id, program, &receiver, route, network));
+                    (id, thread)
+                })
+                .collect();
+            threads
+                .into_iter()
+                .map(|(id, thread)| (id, thread.join().unwrap()))
+                .collect()
+        });
+
+        for (id, (outputs, result)) in results {
+            self.outputs[id].extend(outputs);
+            self.halted[id] = result.map_err(|error| SchedulerError { program: id, error })?;
+        }
+        Ok(self.halted.iter().all(|&halted| halted))
+    }
+}
+
+/// State shared by the threads of [`Scheduler::run_threaded`].
+///
+/// A value is in flight from when it is sent until it is received. Once
+/// every running program is waiting and nothing is in flight, no program can
+/// make progress, so they are all sent `None` to stop them. Sending happens
+/// with the lock held so the counts are always exact.
+struct Network<W> {
+    /// The input channel of each program still running.
+    senders: Vec<Option<Sender<Option<W>>>>,
+    alive: usize,
+    waiting: usize,
+    in_flight: usize,
+}
+
+impl<W> Network<W> {
+    fn send(&mut self, to: usize, value: W) {
+        // values sent to a program that has stopped are dropped
+        if let Some(sender) = &self.senders[to] {
+            sender.send(Some(value)).unwrap();
+            self.in_flight += 1;
+        }
+    }
+
+    fn stop_if_stuck(&mut self) {
+        if self.alive > 0 && self.waiting == self.alive && self.in_flight == 0 {
+            for sender in self.senders.iter().flatten() {
+                sender.send(None).unwrap();
+            }
+        }
+    }
+}
+
+/// Take the next value for a program, or `None` if it should stop.
+fn receive<W>(receiver: &Receiver<Option<W>>, network: &Mutex<Network<W>>) -> Option<W> {
+    {
+        let mut network = network.lock().unwrap();
+        if let Ok(message) = receiver.try_recv() {
+            if message.is_some() {
+                network.in_flight -= 1;
+            }
+            return message;
+        }
+        network.waiting += 1;
+        network.stop_if_stuck();
+    }
+    let message = receiver.recv().unwrap();
+    let mut network = network.lock().unwrap();
+    network.waiting -= 1;
+    if message.is_some() {
+        network.in_flight -= 1;
+    }
+    message
+}
+
+/// The body of each thread of [`Scheduler::run_threaded`]. Returns the
+/// program's outputs, and whether it halted.
+fn run_connected<W: Word>(
+    id: usize,
+    program: &mut Program<W>,
+    receiver: &Receiver<Option<W>>,
+    route: &[usize],
+    network: &Mutex<Network<W>>,
+) -> (Vec<W>, Result<bool, IntcodeError<W>>) {
+    let mut outputs = Vec::new();
+    let result = loop {
+        match program.run_until_event() {
+            Ok(StepResult::NeedsInput) => match receive(receiver, network) {
+                Some(value) => program.push_input(value),
+                None => break Ok(false),
+            },
+            Ok(StepResult::Output(value)) => {
+                let mut network = network.lock().unwrap();
+                for &to in route {
+                    network.send(to, value.clone());
+                }
+                outputs.push(value);
+            }
+            Ok(StepResult::Halted) => break Ok(true),
+            Ok(StepResult::Continue) => unreachable!(),
+            Err(err) => break Err(err),
+        }
+    };
+
+    // the program has stopped, so values still queued for it will never be
+    // read
+    let mut network = network.lock().unwrap();
+    network.senders[id] = None;
+    while let Ok(message) = receiver.try_recv() {
+        if message.is_some() {
+            network.in_flight -= 1;
+        }
+    }
+    network.alive -= 1;
+    network.stop_if_stuck();
+    (outputs, result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A day 7 feedback loop of amplifiers with the given phase settings.
+    fn amplifiers(memory: &[i64], phases: &[i64]) -> Scheduler {
+        let mut scheduler = Scheduler::new();
+        for &phase in phases {
+            let id = scheduler.add(Program::new(memory.to_vec()));
+            scheduler.push_input(id, phase);
+        }
+        for id in 0..phases.len() {
+            scheduler.connect(id, (id + 1) % phases.len());
+        }
+        scheduler.push_input(0, 0);
+        scheduler
+    }
+
+    #[test]
+    fn day07_examples() {
+        let chain = [
+            3, 15, 3, 16, 1002, 16, 10, 16, 1, 16, 15, 15, 4, 15, 99, 0, 0,
+        ];
+        let feedback = [
+            3, 26, 1001, 26, -4, 26, 3, 27, 1002, 27, 2, 27, 1, 27, 26, 27, 4, 27, 1001, 28, -1,
+            28, 1005, 28, 6, 99, 0, 0, 5,
+        ];
+        for threaded in [false, true] {
+            for (memory, phases, signal) in [
+                (&chain[..], [4, 3, 2, 1, 0], 43210),
+                (&feedback[..], [9, 8, 7, 6, 5], 139629729),
+            ] {
+                let mut scheduler = amplifiers(memory, &phases);
+                let halted = if threaded {
+                    scheduler.run_threaded()
+                } else {
+                    scheduler.run()
+                };
+                assert!(halted.unwrap());
+                assert_eq!(scheduler.outputs(4).last(), Some(&signal));
+            }
+        }
+    }
+
+    #[test]
+    fn waiting() {
+        // the first program doubles its inputs for the second, which adds
+        // pairs of them, so the odd value out leaves both waiting
+        let double = vec![3, 11, 1002, 11, 2, 11, 4, 11, 1105, 1, 0, 0];
+        let add = vec![3, 13, 3, 14, 1, 13, 14, 15, 4, 15, 1105, 1, 0, 0, 0, 0];
+        for threaded in [false, true] {
+            let mut scheduler = Scheduler::new();
+            let first = scheduler.add(Program::new(double.clone()));
+            let second = scheduler.add(Program::new(add.clone()));
+            scheduler.connect(first, second);
+            for value in [1, 2, 3] {
+                scheduler.push_input(first, value);
+            }
+            let halted = if threaded {
+                scheduler.run_threaded()
+            } else {
+                scheduler.run()
+            };
+            assert!(!halted.unwrap());
+            assert_eq!(scheduler.outputs(first), [2, 4, 6]);
+            assert_eq!(scheduler.outputs(second), [6]);
+            assert!(!scheduler.is_halted(first));
+
+            // more input resumes the network where it stopped
+            scheduler.push_input(first, 4);
+            scheduler.run().unwrap();
+            assert_eq!(scheduler.outputs(second), [6, 14]);
+        }
+    }
+
+    #[test]
+    fn errors() {
+        // the second program fails on its first input, while the first
+        // waits for a reply
+        for threaded in [false, true] {
+            let mut scheduler = Scheduler::new();
+            let first = scheduler.add(Program::new(vec![104, 1, 3, 0, 99]));
+            let second = scheduler.add(Program::new(vec![3, 5, 98, 0, 0, 0]));
+            scheduler.connect(first, second);
+            scheduler.connect(second, first);
+            let err = if threaded {
+                scheduler.run_threaded()
+            } else {
+                scheduler.run()
+            }
+            .unwrap_err();
+            assert_eq!(err.program, second);
+            assert!(matches!(
+                err.error,
+                IntcodeError::UnknownOpcode {
+                    ip: 2,
+                    opcode: 98,
+                    ..
+                }
+            ));
+            assert!(err.to_string().starts_with("program 1 failed: "));
+        }
+    }
+}
@@ -0,0 +1,283 @@
+//! Saving and restoring [`Program`] state.
+//!
+//! A snapshot is a text file with a version header followed by one field per
+//! line:
+//!
+//! ```text
+//! intcode-snapshot 2
+//! ip 2
+//! rb 0
+//! overflow checked
+//! kind sparse
+//! inputs 5,6
+//! outputs 42
+//! memory 3,0,4,0,99
+//! sparse 1000000:7
+//! ```
+//!
+//! `memory` holds the contiguous memory from address 0 and `sparse` any
+//! values written far past it, as `addr:value` pairs. `sparse` may be left
+//! out.
+//!
+//! `overflow` is the [`Overflow`] policy (`checked`, `wrapping` or
+//! `saturating`) and `kind` the [`MemoryKind`] (`sparse` or `dense`).
+//! Version 1 snapshots have neither, and load with the defaults.
+//!
+//! Any trace being recorded is not part of the snapshot.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    fs::File,
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    path::Path,
+};
+
+use crate::{
+    format_values, invalid_data, parse_values, Memory, MemoryKind, Overflow, Program, Word,
+};
+
+const MAGIC: &str = "intcode-snapshot";
+const VERSION: u32 = 2;
+
+fn overflow_name(overflow: Overflow) -> &'static str {
+    match overflow {
+        Overflow::Wrapping => "wrapping",
+        Overflow::Checked => "checked",
+        Overflow::Saturating => "saturating",
+    }
+}
+
+fn parse_overflow(name: &str) -> io::Result<Overflow> {
+    match name {
+        "wrapping" => Ok(Overflow::Wrapping),
+        "checked" => Ok(Overflow::Checked),
+        "saturating" => Ok(Overflow::Saturating),
+        _ => Err(invalid_data(format!("invalid overflow policy '{name}'"))),
+    }
+}
+
+fn kind_name(kind: MemoryKind) -> &'static str {
+    match kind {
+        MemoryKind::Dense => "dense",
+        MemoryKind::Sparse => "sparse",
+    }
+}
+
+fn parse_kind(name: &str) -> io::Result<MemoryKind> {
+    match name {
+        "dense" => Ok(MemoryKind::Dense),
+        "sparse" => Ok(MemoryKind::Sparse),
+        _ => Err(invalid_data(format!("invalid memory kind '{name}'"))),
+    }
+}
+
+/// A saved program along with outputs the host has not consumed yet.
+#[derive(Clone)]
+pub struct Snapshot<W = i64> {
+    pub program: Program<W>,
+    pub outputs: Vec<W>,
+}
+
+impl<W: Word> Snapshot<W> {
+    pub fn write_to<T: Write>(&self, mut writer: T) -> io::Result<()> {
+        let program = &self.program;
+        writeln!(writer, "{MAGIC} {VERSION}")?;
+        writeln!(writer, "ip {}", program.ip)?;
+        writeln!(writer, "rb {}", program.relative_base)?;
+        writeln!(writer, "overflow {}", overflow_name(program.overflow))?;
+        writeln!(writer, "kind {}", kind_name(program.memory.kind()))?;
+        writeln!(writer, "inputs {}", format_values(&program.inputs))?;
+        writeln!(writer, "outputs {}", format_values(&self.outputs))?;
+        writeln!(writer, "memory {}", program.memory.format_dense())?;
+        writeln!(writer, "sparse {}", program.memory.format_sparse())
+    }
+
+    pub fn read_from<R: BufRead>(reader: R) -> io::Result<Snapshot<W>> {
+        let mut lines = reader.lines();
+        let header = lines
+            .next()
+            .unwrap_or_else(|| Err(invalid_data("empty snapshot")))?;
+        let version = header
+            .strip_prefix(MAGIC)
+            .and_then(|version| version.trim().parse::<u32>().ok())
+            .ok_or_else(|| invalid_data("not an intcode snapshot"))?;
+        if !(1..=VERSION).contains(&version) {
+            return Err(invalid_data(format!(
+                "unsupported snapshot version {version}"
+            )));
+        }
+
+        let mut fields = HashMap::new();
+        for line in lines {
+            let line = line?;
+            let (key, value) = line.split_once(' ').unwrap_or((&line, ""));
+            fields.insert(key.to_string(), value.to_string());
+        }
+        let mut field = |key: &str| {
+            fields
+                .remove(key)
+                .ok_or_else(|| invalid_data(format!("snapshot is missing '{key}'")))
+        };
+        let ip = field("ip")?;
+        let relative_base = field("rb")?;
+        let inputs = field("inputs")?;
+        let outputs = field("outputs")?;
+        let memory = field("memory")?;
+        let (overflow, kind) = if version == 1 {
+            (Overflow::default(), MemoryKind::default())
+        } else {
+            (
+                parse_overflow(&field("overflow")?)?,
+                parse_kind(&field("kind")?)?,
+            )
+        };
+        let sparse = fields.remove("sparse").unwrap_or_default();
+
+        let mut program = Program::from(Vec::new());
+        program.memory = Memory::parse(&memory, &sparse, kind)?;
+        program.overflow = overflow;
+        program.ip = ip
+            .parse()
+            .map_err(|_| invalid_data(format!("invalid ip '{ip}'")))?;
+        program.relative_base = relative_base
+            .parse()
+            .map_err(|_| invalid_data(format!("invalid relative base '{relative_base}'")))?;
+        program.inputs = VecDeque::from(parse_values(&inputs)?);
+        Ok(Snapshot {
+            program,
+            outputs: parse_values(&outputs)?,
+        })
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write_to(&mut writer)?;
+        writer.flush()
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Snapshot<W>> {
+        Snapshot::read_from(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<W: Word> Program<W> {
+    /// Save the program state, including queued inputs, to `path`.
+    pub fn save_snapshot<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        Snapshot {
+            program: self.clone(),
+            outputs: Vec::new(),
+        }
+        .save(path)
+    }
+
+    /// Restore a program saved with [`Program::save_snapshot`]. Use
+    /// [`Snapshot::load`] to also get any saved outputs.
+    pub fn load_snapshot<P: AsRef<Path>>(path: P) -> io::Result<Program<W>> {
+        Snapshot::load(path).map(|snapshot| snapshot.program)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::StepResult;
+
+    #[test]
+    fn round_trip() {
+        // output the sum of pairs of inputs forever
+        let mut program = Program::new(vec![
+            109, 20, 203, 0, 203, 1, 22201, 0, 1, 2, 204, 2, 1105, 1, 2,
+        ]);
+        program.push_input(3);
+        program.push_input(4);
+        assert_eq!(program.run_until_event().unwrap(), StepResult::Output(7));
+        program.push_input(10);
+
+        let snapshot = Snapshot {
+            program: program.clone(),
+            outputs: vec![7],
+        };
+        let mut text = Vec::new();
+        snapshot.write_to(&mut text).unwrap();
+        assert_eq!(
+            String::from_utf8(text.clone()).unwrap(),
+            "intcode-snapshot 2\nip 12\nrb 20\noverflow checked\nkind sparse\n\
+             inputs 10\noutputs 7\n\
+             memory 109,20,203,0,203,1,22201,0,1,2,204,2,1105,1,2,0,0,0,0,0,3,4,7\n\
+             sparse \n"
+        );
+
+        let Snapshot {
+            program: mut restored,
+            outputs,
+        } = Snapshot::read_from(text.as_slice()).unwrap();
+        assert_eq!(outputs, [7]);
+        assert_eq!(restored.memory(), program.memory());
+        for mut program in [program, restored.clone()] {
+            program.push_input(20);
+            assert_eq!(program.run_until_event().unwrap(), StepResult::Output(30));
+        }
+        restored.push_input(1);
+        assert_eq!(restored.run_until_event().unwrap(), StepResult::Output(11));
+    }
+
+    #[test]
+    fn bad_snapshots() {
+        let read = |text: &str| {
+            Snapshot::<i64>::read_from(text.as_bytes())
+                .err()
+                .unwrap()
+                .to_string()
+        };
+        assert_eq!(read(""), "empty snapshot");
+        assert_eq!(read("hello"), "not an intcode snapshot");
+        assert_eq!(
+            read("intcode-snapshot 3\n"),
+            "unsupported snapshot version 3"
+        );
+        assert_eq!(
+            read("intcode-snapshot 1\nip 0\nrb 0\ninputs\noutputs\n"),
+            "snapshot is missing 'memory'"
+        );
+        assert_eq!(
+            read("intcode-snapshot 2\nip 0\nrb 0\ninputs\noutputs\nmemory 99\n"),
+            "snapshot is missing 'overflow'"
+        );
+        assert_eq!(
+            read(
+                "intcode-snapshot 2\nip 0\nrb 0\noverflow loose\nkind sparse\n\
+                 inputs\noutputs\nmemory 99\n"
+            ),
+            "invalid overflow policy 'loose'"
+        );
+    }
+
+    #[test]
+    fn settings() {
+        for overflow in [Overflow::Wrapping, Overflow::Checked, Overflow::Saturating] {
+            for kind in [MemoryKind::Dense, MemoryKind::Sparse] {
+                let mut program = Program::<i64>::with_memory_kind(vec![1, 0, 0, 0, 99], kind);
+                program.set_overflow(overflow);
+                let mut text = Vec::new();
+                Snapshot {
+                    program,
+                    outputs: Vec::new(),
+                }
+                .write_to(&mut text)
+                .unwrap();
+                let restored = Snapshot::<i64>::read_from(text.as_slice()).unwrap().program;
+                assert_eq!(restored.overflow(), overflow);
+                assert_eq!(restored.memory().kind(), kind);
+            }
+        }
+
+        // version 1 snapshots had neither setting
+        let restored = Snapshot::<i64>::read_from(
+            "intcode-snapshot 1\nip 0\nrb 0\ninputs\noutputs\nmemory 99\n".as_bytes(),
+        )
+        .unwrap()
+        .program;
+        assert_eq!(restored.overflow(), Overflow::Checked);
+        assert_eq!(restored.memory().kind(), MemoryKind::Sparse);
+    }
+}
@@ -0,0 +1,264 @@
+//! Declarative wiring of programs into pipelines and feedback loops.
+//!
+//! A [`Topology`] is a directed graph: each node is a program with the
+//! inputs it starts with, and each edge sends every output of one node to
+//! another. Nodes with several outgoing edges send each output along all of
+//! them, and nodes with several incoming edges read values in the order they
+//! arrive. Each pair of nodes can be connected at most once in each
+//! direction. A topology can be run any number of times, each run starting
+//! from fresh copies of its programs.
+
+use crate::{Program, Scheduler, SchedulerError, Word};
+
+#[derive(Clone)]
+pub struct Topology<W = i64> {
+    nodes: Vec<Program<W>>,
+    edges: Vec<(usize, usize)>,
+}
+
+impl<W: Word> Default for Topology<W> {
+    fn default() -> Topology<W> {
+        Topology {
+            nodes: Vec::new(),
+            edges: Vec::new(),
+        }
+    }
+}
+
+impl<W: Word> Topology<W> {
+    #[must_use]
+    pub fn new() -> Topology<W> {
+        Topology::default()
+    }
+
+    /// Add a program that reads `inputs` before anything sent to it, and
+    /// return its node id. Ids count up from 0.
+    pub fn node<I: IntoIterator<Item = W>>(&mut self, mut program: Program<W>, inputs: I) -> usize {
+        program.inputs_mut().extend(inputs);
+        self.nodes.push(program);
+        self.nodes.len() - 1
+    }
+
+    /// Add more starting inputs for a node.
+    pub fn seed<I: IntoIterator<Item = W>>(&mut self, node: usize, inputs: I) {
+        self.nodes[node].inputs_mut().extend(inputs);
+    }
+
+    /// Connect `from` to `to` and return the edge id.
+    ///
+    /// # Panics
+    ///
+    /// If either node doesn't exist, or the nodes are already connected this
+    /// way.
+    pub fn edge(&mut self, from: usize, to: usize) -> usize {
+        assert!(
+            from < self.nodes.len() && to < self.nodes.len(),
+            "no node {}",
+            from.max(to)
+        );
+        assert!(
+            !self.edges.contains(&(from, to)),
+            "duplicate edge {from} -> {to}"
+        );
+        self.edges.push((from, to));
+        self.edges.len() - 1
+    }
+
+    /// Connect each node to the next, returning the edge ids.
+    pub fn chain(&mut self, nodes: &[usize]) -> Vec<usize> {
+        nodes
+            .windows(2)
+            .map(|pair| self.edge(pair[0], pair[1]))
+            .collect()
+    }
+
+    /// Connect each node to the next and the last back to the first,
+    /// returning the edge ids. The feedback edge is last.
+    pub fn cycle(&mut self, nodes: &[usize]) -> Vec<usize> {
+        let mut edges = self.chain(nodes);
+        if let (Some(&first), Some(&last)) = (nodes.first(), nodes.last()) {
+            edges.push(self.edge(last, first));
+        }
+        edges
+    }
+
+    /// A [`Scheduler`] with copies of the programs, connected by the edges.
+    #[must_use]
+    pub fn build(&self) -> Scheduler<W> {
+        let mut scheduler = Scheduler::new();
+        for program in &self.nodes {
+            scheduler.add(program.clone());
+        }
+        for &(from, to) in &self.edges {
+            scheduler.connect(from, to);
+        }
+        scheduler
+    }
+
+    /// Run until every node halts or the network is stuck waiting for input,
+    /// using [`Scheduler::run`].
+    pub fn run(&self) -> Result<Finished<W>, SchedulerError<W>> {
+        let mut scheduler = self.build();
+        let halted = scheduler.run()?;
+        Ok(self.finished(scheduler, halted))
+    }
+
+    /// Run like [`Topology::run`] with a thread per node, using
+    /// [`Scheduler::run_threaded`].
+    pub fn run_threaded(&self) -> Result<Finished<W>, SchedulerError<W>> {
+        let mut scheduler = self.build();
+        let halted = scheduler.run_threaded()?;
+        Ok(self.finished(scheduler, halted))
+    }
+
+    fn finished(&self, scheduler: Scheduler<W>, halted: bool) -> Finished<W> {
+        Finished {
+            scheduler,
+            edges: self.edges.clone(),
+            halted,
+        }
+    }
+}
+
+/// The state of a [`Topology`] after a run.
+pub struct Finished<W = i64> {
+    scheduler: Scheduler<W>,
+    edges: Vec<(usize, usize)>,
+    halted: bool,
+}
+
+impl<W: Word> Finished<W> {
+    /// Whether every node halted, rather than the rest all waiting for
+    /// input.
+    #[must_use]
+    pub fn halted(&self) -> bool {
+        self.halted
+    }
+
+    /// Every value sent along an edge. These are all the outputs of the
+    /// edge's source, since each output goes along every edge out of it,
+    /// including values sent to a target that had already halted and so
+    /// never read them.
+    #[must_use]
+    pub fn values(&self, edge: usize) -> &[W] {
+        self.scheduler.outputs(self.edges[edge].0)
+    }
+
+    /// The last value sent along an edge.
+    #[must_use]
+    pub fn last(&self, edge: usize) -> Option<&W> {
+        self.values(edge).last()
+    }
+
+    /// Every value a node output, including nodes with no outgoing edges.
+    #[must_use]
+    pub fn outputs(&self, node: usize) -> &[W] {
+        self.scheduler.outputs(node)
+    }
+
+    #[must_use]
+    pub fn program(&self, node: usize) -> &Program<W> {
+        self.scheduler.program(node)
+    }
+
+    /// The scheduler the run used, to continue running it.
+    #[must_use]
+    pub fn into_scheduler(self) -> Scheduler<W> {
+        self.scheduler
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm;
+
+    /// Output each input multiplied by `factor`.
+    fn times(factor: i64) -> Program {
+        let source = format!(
+            "
+            loop:   IN [x]
+                    MUL [x], #{factor}, [x]
+                    OUT [x]
+                    JT #1, #loop
+            x:      .data 0
+            "
+        );
+        Program::new(asm::assemble(&source).unwrap())
+    }
+
+    #[test]
+    fn fan_out_and_merge() {
+        // doubled and tripled copies of the inputs are added in pairs
+        let add_pairs = asm::assemble(
+            "
+            loop:   IN [a]
+                    IN [b]
+                    ADD [a], [b], [a]
+                    OUT [a]
+                    JT #1, #loop
+            a:      .data 0
+            b:      .data 0
+            ",
+        )
+        .unwrap();
+        let mut topology = Topology::new();
+        let source = topology.node(times(1), [1, 2, 3]);
+        let double = topology.node(times(2), []);
+        let triple = topology.node(times(3), []);
+        let sum = topology.node(Program::new(add_pairs), []);
+        topology.edge(source, double);
+        topology.edge(source, triple);
+        let doubled = topology.edge(double, sum);
+        let tripled = topology.edge(triple, sum);
+
+        // the doubled values all arrive first when running in turn
+        let finished = topology.run().unwrap();
+        assert!(!finished.halted());
+        assert_eq!(finished.values(doubled), [2, 4, 6]);
+        assert_eq!(finished.last(tripled), Some(&9));
+        assert_eq!(finished.outputs(sum), [6, 9, 15]);
+
+        let finished = topology.run_threaded().unwrap();
+        assert!(!finished.halted());
+        assert_eq!(finished.values(tripled), [3, 6, 9]);
+        assert_eq!(finished.outputs(sum).iter().sum::<i64>(), 30);
+    }
+
+    #[test]
+    fn feedback() {
+        // a day 7 feedback loop example
+        let amplifier = Program::new(vec![
+            3, 26, 1001, 26, -4, 26, 3, 27, 1002, 27, 2, 27, 1, 27, 26, 27, 4, 27, 1001, 28, -1,
+            28, 1005, 28, 6, 99, 0, 0, 5,
+        ]);
+        let mut topology = Topology::new();
+        let nodes: Vec<_> = [9, 8, 7, 6, 5]
+            .into_iter()
+            .map(|phase| topology.node(amplifier.clone(), [phase]))
+            .collect();
+        let edges = topology.cycle(&nodes);
+        topology.seed(nodes[0], [0]);
+        for threaded in [false, true] {
+            let finished = if threaded {
+                topology.run_threaded()
+            } else {
+                topology.run()
+            }
+            .unwrap();
+            assert!(finished.halted());
+            assert_eq!(finished.last(edges[4]), Some(&139629729));
+            assert_eq!(finished.values(edges[0]).len(), 5);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "duplicate edge 0 -> 1")]
+    fn duplicate_edge() {
+        let mut topology = Topology::new();
+        let nodes = [topology.node(times(1), []), topology.node(times(2), [])];
+        topology.edge(nodes[0], nodes[1]);
+        topology.edge(nodes[1], nodes[0]);
+        topology.edge(nodes[0], nodes[1]);
+    }
+}
@@ -0,0 +1,407 @@
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    path::Path,
+};
+
+use crate::{
+    invalid_data, Instruction, IntcodeError, Memory, MemoryKind, Program, StepResult, Word,
+};
+
+/// A memory write made by one instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryWrite<W = i64> {
+    pub addr: usize,
+    pub old: W,
+    pub new: W,
+}
+
+/// Everything one executed instruction read and changed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceEntry<W = i64> {
+    pub ip: usize,
+    pub instruction: W,
+    /// The resolved value of each parameter: the value read, or the address
+    /// written for write parameters. `None` if the parameter could not be
+    /// resolved, like the target of a jump that was not taken.
+    pub operands: Vec<Option<W>>,
+    /// The memory writes in the order they were made. Built-in
+    /// instructions make at most one, custom opcodes any number.
+    pub writes: Vec<MemoryWrite<W>>,
+    /// The old and new relative base, if it changed.
+    pub relative_base: Option<(W, W)>,
+    pub next_ip: usize,
+}
+
+/// A recording of every instruction executed since tracing started, along
+/// with the state tracing started from.
+#[derive(Debug, Clone)]
+pub struct Trace<W = i64> {
+    ip: usize,
+    relative_base: W,
+    memory: Memory<W>,
+    entries: Vec<TraceEntry<W>>,
+}
+
+impl<W: Word> PartialEq for Trace<W> {
+    fn eq(&self, other: &Trace<W>) -> bool {
+        self.ip == other.ip
+            && self.relative_base == other.relative_base
+            && self.memory == other.memory
+            && self.entries == other.entries
+    }
+}
+
+impl<W: Word> Eq for Trace<W> {}
+
+const HEADER: &str = "intcode-trace 1";
+
+/// The result of a step along with the record of what it did, if it ran.
+type Recorded<W> = (StepResult<W>, Option<TraceEntry<W>>);
+
+fn parse_num<T: std::str::FromStr>(text: &str) -> io::Result<T> {
+    text.parse()
+        .map_err(|_| invalid_data(format!("invalid number '{text}' in trace")))
+}
+
+fn parse_pair<W: Word>(text: &str) -> io::Result<(W, W)> {
+    let (a, b) = text
+        .split_once(':')
+        .ok_or_else(|| invalid_data(format!("invalid pair '{text}' in trace")))?;
+    Ok((parse_num(a)?, parse_num(b)?))
+}
+
+impl<W: Word> TraceEntry<W> {
+    fn parse(line: &str) -> io::Result<TraceEntry<W>> {
+        let mut fields = line.split_whitespace();
+        let mut next_field = || {
+            fields
+                .next()
+                .ok_or_else(|| invalid_data(format!("truncated trace line '{line}'")))
+        };
+        let ip = parse_num(next_field()?)?;
+        let instruction = parse_num(next_field()?)?;
+        let _mnemonic = next_field()?;
+        let mut entry = TraceEntry {
+            ip,
+            instruction,
+            operands: Vec::new(),
+            writes: Vec::new(),
+            relative_base: None,
+            next_ip: ip,
+        };
+        for field in fields {
+            let (key, value) = field
+                .split_once('=')
+                .ok_or_else(|| invalid_data(format!("invalid trace field '{field}'")))?;
+            match key {
+                "args" => {
+                    entry.operands = value
+                        .split(',')
+                        .map(|arg| match arg {
+                            "?" => Ok(None),
+                            _ => parse_num(arg).map(Some),
+                        })
+                        .collect::<io::Result<_>>()?;
+                }
+                "w" => {
+                    let (addr, rest) = value
+                        .split_once(':')
+                        .ok_or_else(|| invalid_data(format!("invalid write '{value}'")))?;
+                    let (old, new) = parse_pair(rest)?;
+                    entry.writes.push(MemoryWrite {
+                        addr: parse_num(addr)?,
+                        old,
+                        new,
+                    });
+                }
+                "rb" => entry.relative_base = Some(parse_pair(value)?),
+                "next" => entry.next_ip = parse_num(value)?,
+                _ => return Err(invalid_data(format!("unknown trace field '{key}'"))),
+            }
+        }
+        Ok(entry)
+    }
+}
+
+impl<W: Word> std::fmt::Display for TraceEntry<W> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mnemonic = self
+            .instruction
+            .to_i64()
+            .and_then(|word| Instruction::decode(word).ok())
+            .map_or("?", |instruction| instruction.opcode.mnemonic());
+        write!(f, "{} {} {mnemonic}", self.ip, self.instruction)?;
+        if !self.operands.is_empty() {
+            let operands: Vec<_> = self
+                .operands
+                .iter()
+                .map(|operand| {
+                    operand
+                        .as_ref()
+                        .map_or("?".to_string(), |value| value.to_string())
+                })
+                .collect();
+            write!(f, " args={}", operands.join(","))?;
+        }
+        for MemoryWrite { addr, old, new } in &self.writes {
+            write!(f, " w={addr}:{old}:{new}")?;
+        }
+        if let Some((old, new)) = &self.relative_base {
+            write!(f, " rb={old}:{new}")?;
+        }
+        write!(f, " next={}", self.next_ip)
+    }
+}
+
+impl<W: Word> Trace<W> {
+    fn new(program: &Program<W>) -> Trace<W> {
+        Trace {
+            ip: program.ip,
+            relative_base: program.relative_base.clone(),
+            memory: program.memory.clone(),
+            entries: Vec::new(),
+        }
+    }
+
+    #[must_use]
+    pub fn entries(&self) -> &[TraceEntry<W>] {
+        &self.entries
+    }
+
+    pub(crate) fn push(&mut self, entry: TraceEntry<W>) {
+        self.entries.push(entry);
+    }
+
+    /// Re-create the program state after the first `step` entries by applying
+    /// their recorded effects to the starting state.
+    ///
+    /// Returns `None` if the trace has fewer than `step` entries.
+    #[must_use]
+    pub fn state_at(&self, step: usize) -> Option<Program<W>> {
+        let entries = self.entries.get(..step)?;
+        let mut program = Program::from(Vec::new());
+        program.memory = self.memory.clone();
+        program.ip = self.ip;
+        program.relative_base = self.relative_base.clone();
+        for entry in entries {
+            for write in &entry.writes {
+                program.write(write.addr, write.new.clone());
+            }
+            if let Some((_, relative_base)) = &entry.relative_base {
+                program.relative_base = relative_base.clone();
+            }
+            program.ip = entry.next_ip;
+        }
+        Some(program)
+    }
+
+    /// The index of the first entry where two traces differ, or `None` if
+    /// they are identical.
+    #[must_use]
+    pub fn first_difference(&self, other: &Trace<W>) -> Option<usize> {
+        let index = self
+            .entries
+            .iter()
+            .zip(&other.entries)
+            .position(|(a, b)| a != b);
+        if index.is_some() || self.entries.len() == other.entries.len() {
+            index
+        } else {
+            Some(self.entries.len().min(other.entries.len()))
+        }
+    }
+
+    /// Write the trace as text, one line per executed instruction.
+    pub fn write_to<T: Write>(&self, mut writer: T) -> io::Result<()> {
+        writeln!(writer, "{HEADER}")?;
+        writeln!(writer, "start ip={} rb={}", self.ip, self.relative_base)?;
+        writeln!(writer, "memory {}", self.memory.format_dense())?;
+        writeln!(writer, "sparse {}", self.memory.format_sparse())?;
+        for entry in &self.entries {
+            writeln!(writer, "{entry}")?;
+        }
+        Ok(())
+    }
+
+    pub fn read_from<R: BufRead>(reader: R) -> io::Result<Trace<W>> {
+        let mut lines = reader.lines();
+        let mut next_line = || {
+            lines
+                .next()
+                .unwrap_or_else(|| Err(invalid_data("truncated trace")))
+        };
+        if next_line()? != HEADER {
+            return Err(invalid_data("not an intcode trace"));
+        }
+        let start = next_line()?;
+        let (ip, relative_base) = start
+            .strip_prefix("start ip=")
+            .and_then(|rest| rest.split_once(" rb="))
+            .ok_or_else(|| invalid_data("invalid trace start line"))?;
+        let memory = next_line()?;
+        let memory = memory
+            .strip_prefix("memory ")
+            .ok_or_else(|| invalid_data("invalid trace memory line"))?;
+        let sparse = next_line()?;
+        let sparse = sparse
+            .strip_prefix("sparse ")
+            .ok_or_else(|| invalid_data("invalid trace sparse memory line"))?;
+        let mut trace = Trace {
+            ip: parse_num(ip)?,
+            relative_base: parse_num(relative_base)?,
+            memory: Memory::parse(memory, sparse, MemoryKind::default())?,
+            entries: Vec::new(),
+        };
+        for line in lines {
+            trace.entries.push(TraceEntry::parse(&line?)?);
+        }
+        Ok(trace)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write_to(&mut writer)?;
+        writer.flush()
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Trace<W>> {
+        Trace::read_from(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<W: Word> Program<W> {
+    /// Start recording every executed instruction, discarding any previous
+    /// trace.
+    pub fn start_trace(&mut self) {
+        self.trace = Some(Box::new(Trace::new(self)));
+    }
+
+    /// Stop tracing and return the recorded trace.
+    pub fn take_trace(&mut self) -> Option<Trace<W>> {
+        self.trace.take().map(|trace| *trace)
+    }
+
+    #[must_use]
+    pub fn trace(&self) -> Option<&Trace<W>> {
+        self.trace.as_deref()
+    }
+
+    /// Execute one instruction, also returning a record of its effects if it
+    /// ran.
+    pub(crate) fn step_recorded(&mut self) -> Result<Recorded<W>, IntcodeError<W>> {
+        let ip = self.ip;
+        let instruction = self.read(ip);
+        let relative_base = self.relative_base.clone();
+        let mut operands = Vec::new();
+        let mut write_addr = None;
+        if let Some(Ok(decoded)) = instruction.to_i64().map(Instruction::decode) {
+            for (index, &mode) in decoded.param_modes().iter().enumerate() {
+                let operand = if decoded.opcode.write_param() == Some(index) {
+                    let addr = self.get_addr(index + 1, mode).ok();
+                    write_addr = addr;
+                    addr.and_then(|addr| i64::try_from(addr).ok())
+                        .map(W::from_i64)
+                } else {
+                    self.get_param(index + 1, mode).ok()
+                };
+                operands.push(operand);
+            }
+        }
+        let old = write_addr.map(|addr| self.read(addr));
+
+        let result = self.execute();
+        // writes made by a custom opcode, which may have failed or halted
+        // after making them
+        let custom_writes = std::mem::take(&mut self.custom_writes);
+        let result = result?;
+        let halted = matches!(result, StepResult::NeedsInput | StepResult::Halted);
+        if halted && custom_writes.is_empty() {
+            return Ok((result, None));
+        }
+
+        let mut writes: Vec<_> = write_addr
+            .zip(old)
+            .map(|(addr, old)| MemoryWrite {
+                addr,
+                old,
+                new: self.read(addr),
+            })
+            .into_iter()
+            .collect();
+        writes.extend(custom_writes);
+        let entry = TraceEntry {
+            ip,
+            instruction,
+            operands,
+            writes,
+            relative_base: (relative_base != self.relative_base)
+                .then(|| (relative_base, self.relative_base.clone())),
+            next_ip: self.ip,
+        };
+        Ok((result, Some(entry)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+
+    use super::*;
+
+    fn traced_run(memory: Vec<i64>, inputs: &[i64]) -> Trace {
+        let mut program = Program::new(memory);
+        program.start_trace();
+        program
+            .run(VecDeque::from(inputs.to_vec()), Vec::new())
+            .unwrap();
+        program.take_trace().unwrap()
+    }
+
+    #[test]
+    fn record_and_replay() {
+        let memory = vec![109, 3, 203, 8, 1001, 11, 5, 11, 4, 11, 99, 0];
+        let trace = traced_run(memory.clone(), &[7]);
+        let lines: Vec<_> = trace.entries().iter().map(ToString::to_string).collect();
+        assert_eq!(
+            lines,
+            [
+                "0 109 ARB args=3 rb=0:3 next=2",
+                "2 203 IN args=11 w=11:0:7 next=4",
+                "4 1001 ADD args=7,5,11 w=11:7:12 next=8",
+                "8 4 OUT args=12 next=10",
+            ]
+        );
+
+        let mut program = Program::new(memory);
+        program.push_input(7);
+        for step in 0..=trace.entries().len() {
+            let state = trace.state_at(step).unwrap();
+            assert_eq!(state.ip(), program.ip());
+            assert_eq!(state.relative_base(), program.relative_base());
+            assert_eq!(state.memory(), program.memory());
+            program.step().unwrap();
+        }
+        assert!(trace.state_at(trace.entries().len() + 1).is_none());
+    }
+
+    #[test]
+    fn file_round_trip() {
+        let trace = traced_run(vec![3, 9, 1008, 9, 8, 10, 4, 10, 99, 0, -1], &[8]);
+        let mut text = Vec::new();
+        trace.write_to(&mut text).unwrap();
+        assert_eq!(Trace::read_from(text.as_slice()).unwrap(), trace);
+    }
+
+    #[test]
+    fn first_difference() {
+        let memory = vec![3, 9, 1008, 9, 8, 10, 4, 10, 99, 0, -1];
+        let a = traced_run(memory.clone(), &[8]);
+        let b = traced_run(memory.clone(), &[7]);
+        assert_eq!(a.first_difference(&a), None);
+        assert_eq!(a.first_difference(&b), Some(0));
+        let mut c = a.clone();
+        c.entries.pop();
+        assert_eq!(a.first_difference(&c), Some(2));
+    }
+}
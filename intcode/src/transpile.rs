@@ -0,0 +1,596 @@
+//! Ahead-of-time translation of Intcode programs to Rust.
+//!
+//! [`transpile`] splits the code reachable from address 0 into basic blocks
+//! with [`Cfg`] and emits a self-contained Rust
+//! module with a `Machine` type. `Machine::run` is a state machine that runs
+//! compiled blocks natively and interprets everything else: code only reached
+//! through computed jumps, instructions that would fail, and blocks the
+//! program writes to. A compiled block that gets written at run time is
+//! interpreted from then on.
+//!
+//! The generated module looks like this:
+//!
+//! ```text
+//! pub struct Machine { pub memory: Vec<i64>, pub ip: usize, pub relative_base: i64, .. }
+//!
+//! impl Machine {
+//!     pub fn new() -> Machine;
+//!     pub fn poke(&mut self, addr: usize, value: i64);
+//!     /// Ok(true) once halted, Ok(false) when `input` returns None.
+//!     pub fn run(
+//!         &mut self,
+//!         input: impl FnMut() -> Option<i64>,
+//!         output: impl FnMut(i64),
+//!     ) -> Result<bool, String>;
+//! }
+//! ```
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    fmt::Write,
+};
+
+use crate::{
+    cfg::{BasicBlock, Cfg},
+    Instruction, Opcode, Overflow, ParamMode,
+};
+
+/// Generated Rust source along with how the program was split up.
+#[derive(Debug, Clone)]
+pub struct Transpiled {
+    pub source: String,
+    /// Start addresses of the blocks compiled to Rust.
+    pub compiled: Vec<usize>,
+    /// Start addresses of the blocks left to the interpreter because the
+    /// program writes to them.
+    pub interpreted: Vec<usize>,
+}
+
+fn word(memory: &[i64], addr: usize) -> i64 {
+    memory.get(addr).copied().unwrap_or(0)
+}
+
+/// Whether an instruction can be compiled: it has no negative addresses or
+/// jump targets known ahead of time.
+fn compilable(memory: &[i64], addr: usize, instruction: Instruction) -> bool {
+    instruction
+        .param_modes()
+        .iter()
+        .enumerate()
+        .all(|(index, &mode)| {
+            let is_addr =
+                mode == ParamMode::Position || instruction.opcode.write_param() == Some(index);
+            let is_target = index == 1
+                && matches!(instruction.opcode, Opcode::JumpIfTrue | Opcode::JumpIfFalse);
+            mode == ParamMode::Relative
+                || !(is_addr || is_target)
+                || word(memory, addr + index + 1) >= 0
+        })
+}
+
+/// The basic blocks of the program, cut short before any instruction that
+/// can't be compiled.
+fn find_blocks(memory: &[i64]) -> Vec<BasicBlock> {
+    Cfg::build(memory)
+        .blocks()
+        .map(|block| BasicBlock {
+            start: block.start,
+            instructions: block
+                .instructions
+                .iter()
+                .copied()
+                .take_while(|&(addr, instruction)| compilable(memory, addr, instruction))
+                .collect(),
+        })
+        .filter(|block| !block.instructions.is_empty())
+        .collect()
+}
+
+/// The indices of blocks that must be interpreted: blocks overlapping other
+/// blocks and blocks written by a statically known address.
+fn find_modified(memory: &[i64], blocks: &[BasicBlock]) -> BTreeSet<usize> {
+    let mut modified = BTreeSet::new();
+    let mut owner = HashMap::new();
+    for (index, block) in blocks.iter().enumerate() {
+        for addr in block.start..block.end() {
+            if let Some(other) = owner.insert(addr, index) {
+                modified.extend([index, other]);
+            }
+        }
+    }
+    for block in blocks {
+        for &(addr, instruction) in &block.instructions {
+            let Some(param) = instruction.opcode.write_param() else {
+                continue;
+            };
+            if instruction.modes[param] != ParamMode::Relative {
+                let target = word(memory, addr + param + 1) as usize;
+                modified.extend(owner.get(&target));
+            }
+        }
+    }
+    modified
+}
+
+/// The Rust statements for one instruction of compiled block `id`, with
+/// nested lines indented by four spaces.
+fn emit_instruction(memory: &[i64], id: usize, ip: usize, instruction: Instruction) -> Vec<String> {
+    let raw = |index: usize| word(memory, ip + index + 1);
+    let value = |index: usize| match instruction.modes[index] {
+        ParamMode::Immediate => raw(index).to_string(),
+        ParamMode::Position => format!("self.read({})", raw(index)),
+        ParamMode::Relative => format!("self.read(self.rel({ip}, {})?)", raw(index)),
+    };
+    let addr = |index: usize| match instruction.modes[index] {
+        ParamMode::Relative => format!("self.rel({ip}, {})?", raw(index)),
+        _ => raw(index).to_string(),
+    };
+    let next = ip + instruction.size();
+    let mut lines = Vec::new();
+    let write = |lines: &mut Vec<String>, index: usize, target: &str, value: &str| {
+        lines.push(format!("self.write({target}, {value});"));
+        // relative writes may land in this block, which must then stop
+        if instruction.modes[index] == ParamMode::Relative {
+            lines.extend([
+                format!("if self.invalid[{id}] {{"),
+                format!("    self.ip = {next};"),
+                "    continue;".to_string(),
+                "}".to_string(),
+            ]);
+        }
+    };
+    match instruction.opcode {
+        Opcode::Add | Opcode::Multiply | Opcode::LessThan | Opcode::Equals => {
+            let (a, b) = (value(0), value(1));
+            let result = match instruction.opcode {
+                Opcode::Add => format!("add({ip}, {a}, {b})?"),
+                Opcode::Multiply => format!("mul({ip}, {a}, {b})?"),
+                Opcode::LessThan => format!("i64::from({a} < {b})"),
+                _ => format!("i64::from({a} == {b})"),
+            };
+            write(&mut lines, 2, &addr(2), &result);
+        }
+        Opcode::Input => {
+            // resolve the address before taking input, like the interpreter
+            let target = match instruction.modes[0] {
+                ParamMode::Relative => {
+                    lines.push(format!("let addr = {};", addr(0)));
+                    "addr".to_string()
+                }
+                _ => addr(0),
+            };
+            lines.extend([
+                "let Some(value) = input() else {".to_string(),
+                format!("    self.ip = {ip};"),
+                "    return Ok(false);".to_string(),
+                "};".to_string(),
+            ]);
+            write(&mut lines, 0, &target, "value");
+        }
+        Opcode::Output => lines.push(format!("output({});", value(0))),
+        Opcode::JumpIfTrue | Opcode::JumpIfFalse => {
+            let op = match instruction.opcode {
+                Opcode::JumpIfTrue => "!=",
+                _ => "==",
+            };
+            let target = match instruction.modes[1] {
+                ParamMode::Immediate => raw(1).to_string(),
+                _ => format!("self.jump({ip}, {})?", value(1)),
+            };
+            let jump = [format!("self.ip = {target};"), "continue;".to_string()];
+            match instruction.modes[0] {
+                ParamMode::Immediate if (raw(0) != 0) == (op == "!=") => lines.extend(jump),
+                ParamMode::Immediate => lines.push(format!("self.ip = {next};")),
+                _ => {
+                    lines.push(format!("if {} {op} 0 {{", value(0)));
+                    lines.extend(jump.map(|line| format!("    {line}")));
+                    lines.push("}".to_string());
+                    lines.push(format!("self.ip = {next};"));
+                }
+            }
+        }
+        Opcode::AdjustRelativeBase => lines.push(format!(
+            "self.relative_base = add({ip}, self.relative_base, {})?;",
+            value(0)
+        )),
+        Opcode::Halt => {
+            lines.push(format!("self.ip = {ip};"));
+            lines.push("return Ok(true);".to_string());
+        }
+    }
+    lines
+}
+
+fn format_list<T: ToString>(values: impl IntoIterator<Item = T>) -> String {
+    let values: Vec<_> = values.into_iter().map(|value| value.to_string()).collect();
+    values.join(", ")
+}
+
+/// Translate a program's memory image to a Rust module, with checked
+/// arithmetic like a new [`Program`](crate::Program).
+#[must_use]
+pub fn transpile(memory: &[i64]) -> Transpiled {
+    transpile_with_overflow(memory, Overflow::default())
+}
+
+/// Translate a program's memory image to a Rust module whose arithmetic
+/// follows `overflow`.
+#[must_use]
+pub fn transpile_with_overflow(memory: &[i64], overflow: Overflow) -> Transpiled {
+    let blocks = find_blocks(memory);
+    let modified = find_modified(memory, &blocks);
+    let compiled: Vec<_> = blocks
+        .iter()
+        .enumerate()
+        .filter(|(index, _)| !modified.contains(index))
+        .map(|(_, block)| block)
+        .collect();
+
+    let table_len = compiled
+        .iter()
+        .map(|block| block.end())
+        .max()
+        .unwrap_or(0)
+        .max(memory.len());
+    let mut block_of = vec![u32::MAX; table_len];
+    let mut arms = String::new();
+    for (id, block) in compiled.iter().enumerate() {
+        block_of[block.start..block.end()].fill(id as u32);
+        let mut lines: Vec<_> = block
+            .instructions
+            .iter()
+            .flat_map(|&(ip, instruction)| emit_instruction(memory, id, ip, instruction))
+            .collect();
+        let (last, instruction) = *block.instructions.last().unwrap();
+        let ends_block = matches!(
+            instruction.opcode,
+            Opcode::Halt | Opcode::JumpIfTrue | Opcode::JumpIfFalse
+        );
+        if !ends_block {
+            lines.push(format!("self.ip = {};", last + instruction.size()));
+        }
+        let indent = " ".repeat(16);
+        writeln!(arms, "{indent}{} if !self.invalid[{id}] => {{", block.start).unwrap();
+        for line in lines {
+            writeln!(arms, "{indent}    {line}").unwrap();
+        }
+        writeln!(arms, "{indent}}}").unwrap();
+    }
+
+    let source = RUNTIME
+        .replace("{len}", &memory.len().to_string())
+        .replace("{image}", &format_list(memory))
+        .replace("{table_len}", &table_len.to_string())
+        .replace(
+            "{block_of}",
+            &format_list(block_of.iter().map(|&id| match id {
+                u32::MAX => "NONE".to_string(),
+                id => id.to_string(),
+            })),
+        )
+        .replace("{add_fn}", &overflow.rust_fn("add", "add"))
+        .replace("{mul_fn}", &overflow.rust_fn("mul", "mul"))
+        .replace("{blocks}", &compiled.len().to_string())
+        .replace("{arms}", &arms);
+    Transpiled {
+        source,
+        compiled: compiled.iter().map(|block| block.start).collect(),
+        interpreted: modified.iter().map(|&index| blocks[index].start).collect(),
+    }
+}
+
+const RUNTIME: &str = r#"// Generated by intcode::transpile from a {len} word program.
+
+const IMAGE: [i64; {len}] = [{image}];
+
+const NONE: u32 = u32::MAX;
+
+/// The compiled block containing each address, or `NONE`.
+const BLOCK_OF: [u32; {table_len}] = [{block_of}];
+
+{add_fn}
+
+{mul_fn}
+
+pub struct Machine {
+    pub memory: Vec<i64>,
+    pub ip: usize,
+    pub relative_base: i64,
+    /// Compiled blocks that have been written to and must be interpreted.
+    invalid: [bool; {blocks}],
+}
+
+impl Default for Machine {
+    fn default() -> Machine {
+        Machine::new()
+    }
+}
+
+impl Machine {
+    pub fn new() -> Machine {
+        Machine {
+            memory: IMAGE.to_vec(),
+            ip: 0,
+            relative_base: 0,
+            invalid: [false; {blocks}],
+        }
+    }
+
+    /// Overwrite the value at `addr`. Compiled code containing `addr` is
+    /// interpreted from then on.
+    pub fn poke(&mut self, addr: usize, value: i64) {
+        self.write(addr, value);
+    }
+
+    fn read(&self, addr: usize) -> i64 {
+        self.memory.get(addr).copied().unwrap_or(0)
+    }
+
+    fn write(&mut self, addr: usize, value: i64) {
+        if let Some(&block) = BLOCK_OF.get(addr) {
+            if block != NONE {
+                self.invalid[block as usize] = true;
+            }
+        }
+        if addr >= self.memory.len() {
+            self.memory.resize(addr + 1, 0);
+        }
+        self.memory[addr] = value;
+    }
+
+    fn rel(&self, ip: usize, offset: i64) -> Result<usize, String> {
+        let addr = add(ip, self.relative_base, offset)?;
+        usize::try_from(addr).map_err(|_| format!("negative address {addr} at {ip}"))
+    }
+
+    fn jump(&self, ip: usize, target: i64) -> Result<usize, String> {
+        usize::try_from(target).map_err(|_| format!("invalid jump target {target} at {ip}"))
+    }
+
+    fn addr(&self, offset: usize, mode: i64) -> Result<usize, String> {
+        let word = self.read(self.ip + offset);
+        match mode {
+            0 | 1 => usize::try_from(word)
+                .map_err(|_| format!("negative address {word} at {}", self.ip)),
+            2 => self.rel(self.ip, word),
+            _ => Err(format!("unknown parameter mode {mode} at {}", self.ip)),
+        }
+    }
+
+    fn param(&self, offset: usize, mode: i64) -> Result<i64, String> {
+        match mode {
+            1 => Ok(self.read(self.ip + offset)),
+            _ => Ok(self.read(self.addr(offset, mode)?)),
+        }
+    }
+
+    /// Interpret one instruction. Returns `Some(true)` on halting and
+    /// `Some(false)` when input is needed but there is none.
+    fn step(
+        &mut self,
+        input: &mut impl FnMut() -> Option<i64>,
+        output: &mut impl FnMut(i64),
+    ) -> Result<Option<bool>, String> {
+        let ip = self.ip;
+        let instruction = self.read(ip);
+        if instruction < 0 {
+            return Err(format!("invalid instruction {instruction} at {ip}"));
+        }
+        let mode = |n: u32| instruction / 10i64.pow(n + 1) % 10;
+        match instruction % 100 {
+            op @ (1 | 2 | 7 | 8) => {
+                let a = self.param(1, mode(1))?;
+                let b = self.param(2, mode(2))?;
+                let addr = self.addr(3, mode(3))?;
+                let value = match op {
+                    1 => add(ip, a, b)?,
+                    2 => mul(ip, a, b)?,
+                    7 => i64::from(a < b),
+                    _ => i64::from(a == b),
+                };
+                self.write(addr, value);
+                self.ip += 4;
+            }
+            3 => {
+                let addr = self.addr(1, mode(1))?;
+                let Some(value) = input() else {
+                    return Ok(Some(false));
+                };
+                self.write(addr, value);
+                self.ip += 2;
+            }
+            4 => {
+                output(self.param(1, mode(1))?);
+                self.ip += 2;
+            }
+            op @ (5 | 6) => {
+                if (self.param(1, mode(1))? != 0) == (op == 5) {
+                    let target = self.param(2, mode(2))?;
+                    self.ip = self.jump(ip, target)?;
+                } else {
+                    self.ip += 3;
+                }
+            }
+            9 => {
+                self.relative_base = add(ip, self.relative_base, self.param(1, mode(1))?)?;
+                self.ip += 2;
+            }
+            99 => return Ok(Some(true)),
+            op => return Err(format!("unknown opcode {op} at {ip}")),
+        }
+        Ok(None)
+    }
+
+    /// Run until the program halts, returning `Ok(true)`, or until it needs
+    /// input and `input` returns `None`, returning `Ok(false)`. Running again
+    /// resumes from the input instruction.
+    pub fn run(
+        &mut self,
+        mut input: impl FnMut() -> Option<i64>,
+        mut output: impl FnMut(i64),
+    ) -> Result<bool, String> {
+        loop {
+            match self.ip {
+{arms}                _ => {
+                    if let Some(halted) = self.step(&mut input, &mut output)? {
+                        return Ok(halted);
+                    }
+                }
+            }
+        }
+    }
+}
+"#;
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, process::Command};
+
+    use super::*;
+    use crate::{asm, Program, StepResult};
+
+    /// What [`Program`] does with these inputs, formatted like the test
+    /// harness output.
+    fn reference(memory: &[i64], inputs: &[i64]) -> String {
+        let mut program = Program::new(memory.to_vec());
+        let mut inputs = inputs.iter();
+        let mut outputs = Vec::new();
+        let halted = loop {
+            match program.run_until_event().unwrap() {
+                StepResult::NeedsInput => match inputs.next() {
+                    Some(&value) => program.push_input(value),
+                    None => break false,
+                },
+                StepResult::Output(value) => outputs.push(value),
+                StepResult::Halted => break true,
+                StepResult::Continue => unreachable!(),
+            }
+        };
+        format!("Ok({halted}) {outputs:?} {:?}", program.memory().to_vec())
+    }
+
+    /// Transpile every case into one crate, compile it with rustc and return
+    /// one line of output per case.
+    fn run_transpiled(cases: &[(Vec<i64>, Vec<i64>)]) -> Vec<String> {
+        let mut source = String::new();
+        let mut main = String::from("fn main() {\n");
+        for (i, (memory, inputs)) in cases.iter().enumerate() {
+            writeln!(source, "mod case{i} {{\n{}}}", transpile(memory).source).unwrap();
+            writeln!(
+                main,
+                "    let mut machine = case{i}::Machine::new();
+    let mut inputs = vec!{inputs:?}.into_iter();
+    let mut outputs: Vec<i64> = Vec::new();
+    let result = machine.run(|| inputs.next(), |value| outputs.push(value));
+    println!(\"{{result:?}} {{outputs:?}} {{:?}}\", machine.memory);"
+            )
+            .unwrap();
+        }
+        source.push_str(&main);
+        source.push_str("}\n");
+
+        let dir = env::temp_dir().join(format!("intcode-transpile-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("main.rs");
+        fs::write(&path, source).unwrap();
+        let rustc = env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string());
+        let status = Command::new(rustc)
+            .args(["--edition", "2021", "-A", "warnings", "-o"])
+            .arg(dir.join("main"))
+            .arg(&path)
+            .status()
+            .unwrap();
+        assert!(status.success(), "generated code failed to compile");
+        let output = Command::new(dir.join("main")).output().unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(output.status.success());
+        String::from_utf8(output.stdout)
+            .unwrap()
+            .lines()
+            .map(str::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn blocks() {
+        let memory = asm::assemble(
+            "
+            start:  IN [x]
+                    JF [x], #end
+                    ADD [x], #1, [x]
+                    OUT [x]
+                    JT #1, #start
+            end:    HALT
+            x:      .data 0
+            ",
+        )
+        .unwrap();
+        let transpiled = transpile(&memory);
+        assert_eq!(transpiled.compiled, [0, 5, 14]);
+        assert!(transpiled.interpreted.is_empty());
+
+        // the output instruction's operand is rewritten by the add
+        let memory = vec![104, 0, 1001, 1, 1, 1, 1007, 1, 3, 20, 1005, 20, 0, 99];
+        let transpiled = transpile(&memory);
+        assert_eq!(transpiled.compiled, [13]);
+        assert_eq!(transpiled.interpreted, [0]);
+
+        let source = transpile_with_overflow(&memory, Overflow::Wrapping).source;
+        assert!(source.contains("Ok(a.wrapping_add(b))"));
+        assert!(source.contains("Ok(a.wrapping_mul(b))"));
+    }
+
+    #[test]
+    fn differential() {
+        let compare = vec![
+            3, 21, 1008, 21, 8, 20, 1005, 20, 22, 107, 8, 21, 20, 1006, 20, 31, 1106, 0, 36, 98, 0,
+            0, 1002, 21, 125, 20, 4, 20, 1105, 1, 46, 104, 999, 1105, 1, 46, 1101, 1000, 1, 20, 4,
+            20, 1105, 1, 46, 98, 99,
+        ];
+        let quine = vec![
+            109, 1, 204, -1, 1001, 100, 1, 100, 1008, 100, 16, 101, 1006, 101, 0, 99,
+        ];
+        let counter = vec![104, 0, 1001, 1, 1, 1, 1007, 1, 3, 20, 1005, 20, 0, 99];
+        // patch the operand of the output through the relative base
+        let patch = asm::assemble(
+            "
+                    ARB #out+1
+                    ADD #41, #1, [rb]
+            out:    OUT #0
+                    HALT
+            ",
+        )
+        .unwrap();
+        let increment = asm::assemble(
+            "
+            start:  IN [x]
+                    JF [x], #end
+                    ADD [x], #1, [x]
+                    OUT [x]
+                    JT #1, #start
+            end:    HALT
+            x:      .data 0
+            ",
+        )
+        .unwrap();
+        let far = vec![109, 1000, 203, 0, 204, 0, 99];
+        let big = vec![1102, 34915192, 34915192, 7, 4, 7, 99, 0];
+        let cases = [
+            (compare.clone(), vec![7]),
+            (compare.clone(), vec![8]),
+            (compare, vec![9]),
+            (quine, vec![]),
+            (counter, vec![]),
+            (patch, vec![]),
+            (increment.clone(), vec![4, 9, 0]),
+            (increment, vec![4, 9]),
+            (far, vec![5]),
+            (big, vec![]),
+        ];
+        let lines = run_transpiled(&cases);
+        assert_eq!(lines.len(), cases.len());
+        for ((memory, inputs), line) in cases.iter().zip(lines) {
+            assert_eq!(line, reference(memory, inputs), "program {memory:?}");
+        }
+    }
+}
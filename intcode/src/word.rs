@@ -0,0 +1,230 @@
+use std::{
+    fmt::{Debug, Display},
+    hash::Hash,
+    str::FromStr,
+};
+
+use crate::BigInt;
+
+/// A value stored in Intcode memory.
+///
+/// [`Program`](crate::Program) runs over any word type. `i64` is the
+/// default, `i128` gives more headroom, and [`BigInt`] never overflows.
+/// Instructions, addresses and jump targets must still fit in `i64` and
+/// `usize`.
+pub trait Word:
+    Clone + Default + Eq + Ord + Hash + Debug + Display + FromStr + Send + Sync + 'static
+{
+    fn from_i64(value: i64) -> Self;
+
+    /// The value as an `i64`, or `None` if it doesn't fit.
+    fn to_i64(&self) -> Option<i64>;
+
+    fn checked_add(self, rhs: Self) -> Option<Self>;
+    fn wrapping_add(self, rhs: Self) -> Self;
+    fn saturating_add(self, rhs: Self) -> Self;
+    fn checked_mul(self, rhs: Self) -> Option<Self>;
+    fn wrapping_mul(self, rhs: Self) -> Self;
+    fn saturating_mul(self, rhs: Self) -> Self;
+
+    /// The value as an address, or `None` if it is negative or too large.
+    fn to_usize(&self) -> Option<usize> {
+        self.to_i64().and_then(|value| usize::try_from(value).ok())
+    }
+
+    fn is_zero(&self) -> bool {
+        *self == Self::default()
+    }
+
+    fn is_negative(&self) -> bool {
+        *self < Self::default()
+    }
+}
+
+macro_rules! impl_word {
+    ($($t:ty),*) => {$(
+        impl Word for $t {
+            fn from_i64(value: i64) -> $t {
+                value.into()
+            }
+
+            fn to_i64(&self) -> Option<i64> {
+                i64::try_from(*self).ok()
+            }
+
+            fn checked_add(self, rhs: $t) -> Option<$t> {
+                <$t>::checked_add(self, rhs)
+            }
+
+            fn wrapping_add(self, rhs: $t) -> $t {
+                <$t>::wrapping_add(self, rhs)
+            }
+
+            fn saturating_add(self, rhs: $t) -> $t {
+                <$t>::saturating_add(self, rhs)
+            }
+
+            fn checked_mul(self, rhs: $t) -> Option<$t> {
+                <$t>::checked_mul(self, rhs)
+            }
+
+            fn wrapping_mul(self, rhs: $t) -> $t {
+                <$t>::wrapping_mul(self, rhs)
+            }
+
+            fn saturating_mul(self, rhs: $t) -> $t {
+                <$t>::saturating_mul(self, rhs)
+            }
+        }
+    )*};
+}
+
+impl_word!(i64, i128);
+
+/// Arbitrary precision arithmetic can't overflow, so every policy gives the
+/// exact result.
+impl Word for BigInt {
+    fn from_i64(value: i64) -> BigInt {
+        value.into()
+    }
+
+    fn to_i64(&self) -> Option<i64> {
+        BigInt::to_i64(self)
+    }
+
+    fn checked_add(self, rhs: BigInt) -> Option<BigInt> {
+        Some(self + rhs)
+    }
+
+    fn wrapping_add(self, rhs: BigInt) -> BigInt {
+        self + rhs
+    }
+
+    fn saturating_add(self, rhs: BigInt) -> BigInt {
+        self + rhs
+    }
+
+    fn checked_mul(self, rhs: BigInt) -> Option<BigInt> {
+        Some(self * rhs)
+    }
+
+    fn wrapping_mul(self, rhs: BigInt) -> BigInt {
+        self * rhs
+    }
+
+    fn saturating_mul(self, rhs: BigInt) -> BigInt {
+        self * rhs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        asm,
+        io::{TextInput, TextOutput},
+        IntcodeError, Program,
+    };
+
+    fn load<W: Word>(memory: &[i64]) -> Program<W> {
+        Program::from(
+            memory
+                .iter()
+                .map(|&word| W::from_i64(word))
+                .collect::<Vec<_>>(),
+        )
+    }
+
+    /// Run `memory` with text input and output.
+    fn run_text<W: Word>(memory: &[i64], input: &str) -> Result<String, IntcodeError<W>> {
+        let mut output = TextOutput::new(Vec::new());
+        load::<W>(memory).run(TextInput::new(input.as_bytes()), &mut output)?;
+        Ok(String::from_utf8(output.into_inner()).unwrap())
+    }
+
+    #[test]
+    fn day09_examples() {
+        let quine = [
+            109, 1, 204, -1, 1001, 100, 1, 100, 1008, 100, 16, 101, 1006, 101, 0, 99,
+        ];
+        let square = [1102, 34915192, 34915192, 7, 4, 7, 99, 0];
+        let expected: Vec<_> = quine.iter().map(|word| format!("{word}\n")).collect();
+        assert_eq!(run_text::<i128>(&quine, "").unwrap(), expected.concat());
+        assert_eq!(run_text::<BigInt>(&quine, "").unwrap(), expected.concat());
+        assert_eq!(run_text::<i128>(&square, "").unwrap(), "1219070632396864\n");
+        assert_eq!(
+            run_text::<BigInt>(&square, "").unwrap(),
+            "1219070632396864\n"
+        );
+    }
+
+    #[test]
+    fn factorials() {
+        let factorial = asm::assemble(
+            "
+                    IN [n]
+            loop:   MUL [acc], [n], [acc]
+                    ADD [n], #-1, [n]
+                    JT [n], #loop
+                    OUT [acc]
+                    HALT
+            n:      .data 0
+            acc:    .data 1
+            ",
+        )
+        .unwrap();
+        assert_eq!(
+            run_text::<i64>(&factorial, "20").unwrap(),
+            "2432902008176640000\n"
+        );
+        assert!(matches!(
+            run_text::<i64>(&factorial, "30"),
+            Err(IntcodeError::Overflow { ip: 2, .. })
+        ));
+        assert_eq!(
+            run_text::<i128>(&factorial, "30").unwrap(),
+            "265252859812191058636308480000000\n"
+        );
+        assert!(matches!(
+            run_text::<i128>(&factorial, "40"),
+            Err(IntcodeError::Overflow { ip: 2, .. })
+        ));
+        assert_eq!(
+            run_text::<BigInt>(&factorial, "40").unwrap(),
+            "815915283247897734345611269596115894272000000000\n"
+        );
+    }
+
+    #[test]
+    fn huge_words() {
+        // a word too big for an address, and one too big to decode
+        let mut program = load::<BigInt>(&[1, 0, 0, 0, 99]);
+        let huge: BigInt = "100000000000000000000".parse().unwrap();
+        program.poke(1, huge.clone());
+        let err = program.run(None, Vec::new()).unwrap_err();
+        assert!(matches!(err, IntcodeError::AddressTooLarge { ip: 0, .. }));
+        assert_eq!(
+            err.to_string(),
+            "address 100000000000000000000 too large in instruction 1 at address 0"
+        );
+
+        let mut program = load::<BigInt>(&[0]);
+        program.poke(0, huge);
+        let err = program.step().unwrap_err();
+        assert!(matches!(
+            err,
+            IntcodeError::InvalidInstruction { ip: 0, .. }
+        ));
+
+        // just past i128::MAX, where the conversion used to overflow
+        let mut program = load::<BigInt>(&[0]);
+        program.poke(
+            0,
+            "170141183460469231731687303715884105728".parse().unwrap(),
+        );
+        assert!(matches!(
+            program.step(),
+            Err(IntcodeError::InvalidInstruction { ip: 0, .. })
+        ));
+    }
+}
@@ -1,15 +1,26 @@
-use intcode::Program;
+use intcode::{
+    io::{TextInput, TextOutput},
+    Program, RunOutcome,
+};
+
+/// Enough steps for any example, so a program that never halts fails the
+/// test instead of hanging it.
+const MAX_STEPS: u64 = 10_000_000;
 
 pub fn assert_memory_eq(memory: &[i64], expected: &[i64]) {
     let mut program = Program::new(memory.to_vec());
-    program.run("".as_bytes(), Vec::new());
+    let outcome = program.run_with_fuel(None, Vec::new(), MAX_STEPS).unwrap();
+    assert_eq!(outcome, RunOutcome::Halted);
     assert_eq!(program.memory(), expected);
 }
 
 pub fn assert_output_eq(memory: &[i64], input: &str, expected: &str) {
-    let mut output = Vec::new();
+    let mut output = TextOutput::new(Vec::new());
     let mut program = Program::new(memory.to_vec());
-    program.run(input.as_bytes(), &mut output);
-    let output = String::from_utf8(output).unwrap();
+    let outcome = program
+        .run_with_fuel(TextInput::new(input.as_bytes()), &mut output, MAX_STEPS)
+        .unwrap();
+    assert_eq!(outcome, RunOutcome::Halted);
+    let output = String::from_utf8(output.into_inner()).unwrap();
     assert_eq!(output, expected);
 }